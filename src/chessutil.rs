@@ -1,64 +1,134 @@
-use chess::{Board, ChessMove, MoveGen, Rank};
+use chess::{Board, ChessMove, MoveGen, Piece, Rank};
 use std::fs::{File};
 use std::io::Read;
 use std::fs;
 use std::collections::HashMap;
 use std::collections::hash_map::Entry;
+use crate::evaluation::piece_value;
+use crate::see::see_ge;
+use crate::moveordering::OrderingTables;
 
 
 pub fn make_move_new(board: &Board, cm: ChessMove) -> chess::Board{
-    let mut b2 = board.clone();
+    let mut b2 = *board;
     board.make_move(cm, &mut b2);
     return b2;
 }
 
+pub fn is_quiet(board: &Board, chessmove: ChessMove) -> bool{
+    //not a capture (en passant included) and not a promotion
+    if chessmove.get_promotion().is_some() || board.piece_on(chessmove.get_dest()).is_some(){
+        return false;
+    }
+    let is_en_passant = board.piece_on(chessmove.get_source()) == Some(Piece::Pawn) && chessmove.get_source().get_file() != chessmove.get_dest().get_file();
+    return !is_en_passant;
+}
+
 pub fn output_sorted_move_list(board: &chess::Board) -> Vec<ChessMove>{
-    let movegen = MoveGen::new_legal(&board);
-    
-    let (captures, quiet_moves): (Vec<ChessMove>, Vec<ChessMove>) = movegen
-        .partition(|m| board.piece_on(m.get_dest()).is_some());
-
-    let (pawn_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::Pawn
-        });
-
-    let (knight_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        other_captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::Knight
-        });
-
-    let (bishop_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        other_captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::Bishop
-        });
-
-    let (rook_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        other_captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::Rook
-        });
-
-    let (queen_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        other_captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::Queen
-        });
-
-    let (king_caps,other_captures): (Vec<ChessMove>, Vec<ChessMove>) = 
-        other_captures.iter().partition(|m| {
-            board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move") == chess::Piece::King
-        });
-    
+    return score_and_sort_moves(board, None);
+}
+
+pub fn output_sorted_move_list_with_history(board: &chess::Board, tables: &OrderingTables, ply: usize) -> Vec<ChessMove>{
+    //same as output_sorted_move_list but the quiet moves go killers first, then by history
+    return score_and_sort_moves(board, Some((tables, ply)));
+}
+
+fn score_and_sort_moves(board: &chess::Board, ordering: Option<(&OrderingTables, usize)>) -> Vec<ChessMove>{
+    //scores every move once and sorts on that:
+    //queen promotions, then winning/even captures by MVV-LVA, then quiets, underpromotions, and losing captures last
+    const PROMOTION: i32 = 2_000_000;
+    const GOOD_CAPTURE: i32 = 1_000_000;
+    const FIRST_KILLER: i32 = 900_000;
+    const SECOND_KILLER: i32 = 800_000;
+    const UNDERPROMOTION: i32 = -500_000;
+    const BAD_CAPTURE: i32 = -1_000_000;
+
+    let movegen = MoveGen::new_legal(board);
+    let mut scored: Vec<(i32, ChessMove)> = Vec::with_capacity(movegen.len());
+
+    for m in movegen{
+        let attacker = piece_value(board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move"));
+        let victim = match board.piece_on(m.get_dest()){
+            Some(p) => Some(piece_value(p)),
+            None => {
+                let is_en_passant = board.piece_on(m.get_source()) == Some(Piece::Pawn) && m.get_source().get_file() != m.get_dest().get_file();
+                if is_en_passant {Some(piece_value(Piece::Pawn))} else {None}
+            }
+        };
+
+        let score = match (m.get_promotion(), victim){
+            (Some(Piece::Queen), v) => PROMOTION + v.unwrap_or(0),
+            (Some(_), _) => UNDERPROMOTION,
+            (None, Some(v)) => {
+                let mvv_lva = v * 10 - attacker / 10;
+                if !see_ge(board, m, 0){
+                    BAD_CAPTURE + mvv_lva
+                }else{
+                    GOOD_CAPTURE + mvv_lva
+                }
+            }
+            (None, None) => {
+                match ordering{
+                    Some((tables, ply)) => {
+                        match tables.is_killer(ply, m){
+                            Some(0) => FIRST_KILLER,
+                            Some(_) => SECOND_KILLER,
+                            None => tables.history(board.side_to_move(), m)
+                        }
+                    }
+                    None => 0
+                }
+            }
+        };
+        scored.push((score, m));
+    }
+
+    scored.sort_by_key(|&(score, _)| -score);
+    return scored.into_iter().map(|(_, m)| m).collect();
+}
+
+pub fn output_capture_list(board: &chess::Board) -> Vec<ChessMove>{
+    //captures and queen promotions only, for quiescence
+    //uses target masks so the quiet moves are never generated at all
+    let mut movegen = MoveGen::new_legal(board);
+    let mut moves: Vec<ChessMove> = vec![];
+
+    let mut targets = *board.color_combined(!board.side_to_move());
+    match board.en_passant(){
+        //en_passant() is the pawn that just double pushed, we land on the square behind it
+        Some(sq) => targets |= chess::BitBoard::from_square(sq.uforward(board.side_to_move())),
+        None => {}
+    }
+    movegen.set_iterator_mask(targets);
+    for m in &mut movegen{
+        match m.get_promotion(){
+            Some(p) if p != Piece::Queen => {}//underpromotions aren't worth the nodes down here
+            _ => moves.push(m)
+        }
+    }
 
-    let mut moves: Vec<ChessMove> = Vec::<ChessMove>::new();
-    moves.extend(pawn_caps);
-    moves.extend(knight_caps);
-    moves.extend(bishop_caps);
-    moves.extend(rook_caps);
-    moves.extend(queen_caps);
-    moves.extend(king_caps);
-    moves.extend(other_captures);//should be empty
-    moves.extend(quiet_moves);
+    let promotion_rank = match board.side_to_move(){
+        chess::Color::White => chess::get_rank(Rank::Eighth),
+        chess::Color::Black => chess::get_rank(Rank::First)
+    };
+    movegen.set_iterator_mask(promotion_rank & !*board.combined());
+    for m in &mut movegen{
+        if m.get_promotion() == Some(Piece::Queen){
+            moves.push(m);
+        }
+    }
 
+    //most valuable victim first, cheapest attacker breaks ties
+    moves.sort_by_key(|m| {
+        let victim = match board.piece_on(m.get_dest()){
+            Some(p) => piece_value(p),
+            None if m.get_promotion().is_some() => 0,
+            None => piece_value(Piece::Pawn)//en passant
+        };
+        let attacker = piece_value(board.piece_on(m.get_source()).expect("there should always be a piece at the source of a move"));
+        let promotion = if m.get_promotion().is_some() {piece_value(Piece::Queen)} else {0};
+        -(victim + promotion) * 16 + attacker / 100
+    });
     return moves;
 }
 
@@ -109,18 +179,24 @@ impl BookEntry{
     }
 }
 
-pub fn load_book(filename: &str) -> HashMap<u64,Vec<BookEntry>>{
+pub fn load_book(filename: &str, min_weight: u16) -> Result<HashMap<u64,Vec<BookEntry>>, String>{
+    //errors instead of panicking, the engine can play fine without a book
     
     let mut opening_book: HashMap<u64,Vec<BookEntry>> = HashMap::new();
 
 
     let startpos_hash: u64 = 0x463b96181691fc9c;
     
-    let mut f = File::open(filename).expect("Could not open book file");
-    let metadata = fs::metadata(&filename).expect("could not read book metadata");
+    let mut f = File::open(filename).map_err(|e| format!("could not open book file {filename}: {e}"))?;
+    let metadata = fs::metadata(filename).map_err(|e| format!("could not read book metadata for {filename}: {e}"))?;
+
+    if metadata.len() % 16 != 0{
+        //polyglot entries are exactly 16 bytes, anything else is not a book (or got truncated)
+        return Err(format!("book file {filename} is {} bytes, not a multiple of 16", metadata.len()));
+    }
 
     let mut buffer: Vec<u8> = vec![0;metadata.len() as usize];//should be u8 because metadata returns number of bytes in file
-    f.read(&mut buffer).expect("buffer overflow");
+    f.read_exact(&mut buffer).map_err(|e| format!("could not read book file {filename}: {e}"))?;
 
     let num_entries = metadata.len() / 16;
     let mut num_good_entries = 0;
@@ -144,7 +220,7 @@ pub fn load_book(filename: &str) -> HashMap<u64,Vec<BookEntry>>{
         //dest file: 0,1,2
         let mut dest_file: i32 = 0;
         for idx in 0..3{
-            let bit_idx: i32 = idx + 0;
+            let bit_idx: i32 = idx;
             let power = idx;
             let bit: i32 = (chessmove_bits >> bit_idx&1) as i32;
 
@@ -192,13 +268,13 @@ pub fn load_book(filename: &str) -> HashMap<u64,Vec<BookEntry>>{
         let entry = BookEntry::new(key,cm,weight);
 
         
-        if weight > 100{//an arbitrary cutoff I invented
+        if weight > min_weight{//an arbitrary cutoff I invented, defaults to 100
             match opening_book.entry(key){//still don't really get this bit of code mutability-wise (thanks claude!)
                 Entry::Occupied(mut e) => {
                     e.get_mut().push(entry);
                 }
                 Entry::Vacant(e) => {
-                    e.insert((vec![entry]));
+                    e.insert(vec![entry]);
                 }
             }
 
@@ -210,6 +286,72 @@ pub fn load_book(filename: &str) -> HashMap<u64,Vec<BookEntry>>{
     }
 
 
-    return opening_book;
+    return Ok(opening_book);
+
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    fn capture_list_matches_movegen(fen: &str){
+        //captures (en passant too) and queen promotions, nothing else
+        let board = Board::from_str(fen).expect("test positions should be legal");
+        let mut expected: Vec<ChessMove> = MoveGen::new_legal(&board)
+            .filter(|&m| match m.get_promotion(){
+                Some(p) => p == Piece::Queen,
+                None => !is_quiet(&board, m)
+            })
+            .collect();
+        let mut got = output_capture_list(&board);
+        let generated = got.len();
+        expected.sort_by_key(|m| m.to_string());
+        got.sort_by_key(|m| m.to_string());
+        got.dedup();
+        assert_eq!(got.len(), generated, "duplicate moves in {fen}");
+        assert_eq!(got, expected, "{fen}");
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn en_passant_capture_is_included(){
+        let fen = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        capture_list_matches_movegen(fen);
+        let board = Board::from_str(fen).unwrap();
+        assert!(output_capture_list(&board).contains(&ChessMove::from_str("e5d6").unwrap()));
+
+        capture_list_matches_movegen("4k3/8/8/8/5pP1/8/8/4K3 b - g3 0 2");
+    }
+
+    #[test]
+    fn queen_promotions_with_and_without_capture(){
+        let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        capture_list_matches_movegen(fen);
+        let board = Board::from_str(fen).unwrap();
+        let moves = output_capture_list(&board);
+        assert!(moves.contains(&ChessMove::from_str("a7a8q").unwrap()));
+        assert!(moves.contains(&ChessMove::from_str("a7b8q").unwrap()));
+        //capturing promotion goes first
+        assert_eq!(moves[0], ChessMove::from_str("a7b8q").unwrap());
+
+        capture_list_matches_movegen("4k3/8/8/8/8/8/p7/1N2K3 b - - 0 1");
+    }
+
+    #[test]
+    fn underpromotions_are_left_out(){
+        let board = Board::from_str("1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        let moves = output_capture_list(&board);
+        assert!(moves.iter().all(|m| m.get_promotion().is_none() || m.get_promotion() == Some(Piece::Queen)));
+        assert_eq!(moves.len(), 2);
+    }
+
+    #[test]
+    fn busy_positions(){
+        capture_list_matches_movegen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        capture_list_matches_movegen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1");
+        capture_list_matches_movegen("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        //in check, only the captures that get out of it
+        capture_list_matches_movegen("4k3/8/8/8/8/5n2/8/4K3 w - - 0 1");
+        capture_list_matches_movegen("4k3/8/8/8/8/5n2/6B1/4K3 w - - 0 1");
+    }
+}
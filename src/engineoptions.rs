@@ -0,0 +1,171 @@
+use log::{debug,info,warn,error};
+
+//option names, so a typo is a compile error instead of a panic at search time
+pub const HASH: &str = "Hash";
+pub const THREADS: &str = "Threads";
+pub const MULTI_PV: &str = "MultiPV";
+pub const MOVE_OVERHEAD: &str = "Move Overhead";
+pub const PONDER: &str = "Ponder";
+pub const OWN_BOOK: &str = "OwnBook";
+pub const BOOK_FILE: &str = "BookFile";
+pub const BOOK_MIN_WEIGHT: &str = "Book Min Weight";
+pub const MAX_DEPTH: &str = "Max Depth";
+pub const TIME_DIVISOR: &str = "Time Divisor";
+pub const BASE_TIME_SCALE: &str = "Base Time Scale";
+pub const REVERSE_FUTILITY_MARGIN: &str = "Reverse Futility Margin";
+pub const FUTILITY_MARGIN: &str = "Futility Margin";
+pub const RAZOR_MARGIN: &str = "Razor Margin";
+
+
+pub enum OptionKind{
+    Spin{default: i64, min: i64, max: i64},
+    Check{default: bool},
+    Str{default: String},
+    Combo{default: String, choices: Vec<String>}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionValue{
+    Spin(i64),
+    Check(bool),
+    Str(String)//strings and combos
+}
+
+pub struct EngineOption{
+    pub name: &'static str,
+    pub kind: OptionKind,
+    pub value: OptionValue
+}
+
+impl EngineOption{
+    fn new(name: &'static str, kind: OptionKind) -> Self{
+        let value = match &kind{
+            OptionKind::Spin{default, ..} => OptionValue::Spin(*default),
+            OptionKind::Check{default} => OptionValue::Check(*default),
+            OptionKind::Str{default} => OptionValue::Str(default.clone()),
+            OptionKind::Combo{default, ..} => OptionValue::Str(default.clone())
+        };
+        EngineOption { name: name, kind: kind, value: value }
+    }
+
+    pub fn uci_string(&self) -> String{
+        match &self.kind{
+            OptionKind::Spin{default, min, max} => {
+                return format!("option name {} type spin default {} min {} max {}", self.name, default, min, max);
+            }
+            OptionKind::Check{default} => {
+                return format!("option name {} type check default {}", self.name, default);
+            }
+            OptionKind::Str{default} => {
+                //uci has no way to send an empty string, <empty> is what everyone uses
+                let shown = if default.is_empty() {"<empty>"} else {default.as_str()};
+                return format!("option name {} type string default {}", self.name, shown);
+            }
+            OptionKind::Combo{default, choices} => {
+                let vars: Vec<String> = choices.iter().map(|c| format!("var {c}")).collect();
+                return format!("option name {} type combo default {} {}", self.name, default, vars.join(" "));
+            }
+        }
+    }
+
+    fn parse_value(&self, value: &str) -> Result<OptionValue, String>{
+        match &self.kind{
+            OptionKind::Spin{min, max, ..} => {
+                let v: i64 = value.parse().map_err(|_| format!("{} expects a number, got {}", self.name, value))?;
+                if v < *min || v > *max{
+                    return Err(format!("{} must be between {} and {}, got {}", self.name, min, max, v));
+                }
+                return Ok(OptionValue::Spin(v));
+            }
+            OptionKind::Check{..} => {
+                match value.to_lowercase().as_str(){
+                    "true" => return Ok(OptionValue::Check(true)),
+                    "false" => return Ok(OptionValue::Check(false)),
+                    _ => return Err(format!("{} expects true or false, got {}", self.name, value))
+                }
+            }
+            OptionKind::Str{..} => {
+                if value == "<empty>"{
+                    return Ok(OptionValue::Str(String::new()));
+                }
+                return Ok(OptionValue::Str(value.to_owned()));
+            }
+            OptionKind::Combo{choices, ..} => {
+                match choices.iter().find(|c| c.eq_ignore_ascii_case(value)){
+                    Some(c) => return Ok(OptionValue::Str(c.clone())),
+                    None => return Err(format!("{} must be one of {}, got {}", self.name, choices.join("/"), value))
+                }
+            }
+        }
+    }
+}
+
+
+pub struct EngineOptions{
+    options: Vec<EngineOption>//kept in order so uci lists them the same way every time
+}
+
+impl EngineOptions{
+    pub fn new(book_file: &str) -> Self{
+        //book_file is the default for BookFile, it depends on how we were started
+        let options = vec![
+            EngineOption::new(HASH, OptionKind::Spin{default: 16, min: 1, max: 4096}),
+            EngineOption::new(THREADS, OptionKind::Spin{default: 1, min: 1, max: 64}),
+            EngineOption::new(MULTI_PV, OptionKind::Spin{default: 1, min: 1, max: 64}),
+            EngineOption::new(MOVE_OVERHEAD, OptionKind::Spin{default: 10, min: 0, max: 5000}),
+            EngineOption::new(PONDER, OptionKind::Check{default: false}),//only tells the gui we can, go ponder works either way
+            EngineOption::new(OWN_BOOK, OptionKind::Check{default: true}),
+            EngineOption::new(BOOK_FILE, OptionKind::Str{default: book_file.to_owned()}),
+            EngineOption::new(BOOK_MIN_WEIGHT, OptionKind::Spin{default: 100, min: 0, max: 65535}),
+            EngineOption::new(MAX_DEPTH, OptionKind::Spin{default: 12, min: 1, max: 64}),
+            EngineOption::new(TIME_DIVISOR, OptionKind::Spin{default: 20, min: 1, max: 200}),
+            EngineOption::new(BASE_TIME_SCALE, OptionKind::Spin{default: 5, min: 1, max: 20}),
+            //pruning margins in centipawns per ply of depth, for tuning
+            EngineOption::new(REVERSE_FUTILITY_MARGIN, OptionKind::Spin{default: 80, min: 0, max: 1000}),
+            EngineOption::new(FUTILITY_MARGIN, OptionKind::Spin{default: 100, min: 0, max: 1000}),
+            EngineOption::new(RAZOR_MARGIN, OptionKind::Spin{default: 250, min: 0, max: 2000}),
+        ];
+        EngineOptions { options: options }
+    }
+
+    pub fn uci_lines(&self) -> Vec<String>{
+        return self.options.iter().map(|o| o.uci_string()).collect();
+    }
+
+    fn find(&self, name: &str) -> Option<&EngineOption>{
+        //uci option names are not case sensitive
+        return self.options.iter().find(|o| o.name.eq_ignore_ascii_case(name));
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<&'static str, String>{
+        //returns the canonical name of the option that changed
+        let option = match self.options.iter_mut().find(|o| o.name.eq_ignore_ascii_case(name)){
+            Some(o) => o,
+            None => return Err(format!("no such option: {name}"))
+        };
+        option.value = option.parse_value(value)?;
+        info!("option {} set to {:?}", option.name, option.value);
+        return Ok(option.name);
+    }
+
+    pub fn spin(&self, name: &str) -> i64{
+        match self.find(name).map(|o| &o.value){
+            Some(OptionValue::Spin(v)) => return *v,
+            _ => panic!("{name} is not a spin option")
+        }
+    }
+
+    pub fn check(&self, name: &str) -> bool{
+        match self.find(name).map(|o| &o.value){
+            Some(OptionValue::Check(v)) => return *v,
+            _ => panic!("{name} is not a check option")
+        }
+    }
+
+    pub fn string(&self, name: &str) -> String{
+        match self.find(name).map(|o| &o.value){
+            Some(OptionValue::Str(v)) => return v.clone(),
+            _ => panic!("{name} is not a string or combo option")
+        }
+    }
+}
@@ -3,6 +3,20 @@ use chess::{get_rank, BitBoard, Board, BoardStatus, ChessMove, Color, MoveGen};
 use log::{debug,info,warn,error};
 use crate::chessutil::{self, early_game_probability};
 
+pub type Score = i32;//centipawns
+
+pub fn piece_value(piece: chess::Piece) -> Score{
+    //same material values evaluate_for_color uses, in centipawns, for move ordering and pruning
+    match piece{
+        chess::Piece::Pawn => 100,
+        chess::Piece::Knight => 300,
+        chess::Piece::Bishop => 310,
+        chess::Piece::Rook => 500,
+        chess::Piece::Queen => 900,
+        chess::Piece::King => 10000//never actually captured, but it should never look like a good trade either
+    }
+}
+
 
 
 pub fn forward_pos(row: usize, color: chess::Color) -> usize{
@@ -324,10 +338,10 @@ pub fn evaluate_for_color(board: &Board, color: chess::Color) -> f32{
     
 
     //--------piece square table-----------------
-    let pst_score_mg =  (piece_square_table_evaluate_mg(board, color) as f32) * 0.10;//not sure if .25 is an appropriate weight idk
-    let pst_score_eg =  (piece_square_table_evaluate_eg(board, color) as f32) * 0.10;//not sure if .25 is an appropriate weight idk
+    let pst_score_mg =  piece_square_table_evaluate_mg(board, color) * 0.10;//not sure if .25 is an appropriate weight idk
+    let pst_score_eg =  piece_square_table_evaluate_eg(board, color) * 0.10;//not sure if .25 is an appropriate weight idk
     
-    let eg_prob = chessutil::end_game_probability(&board);
+    let eg_prob = chessutil::end_game_probability(board);
     let pst_score = (eg_prob * pst_score_eg) + ((1.0-eg_prob) * pst_score_mg);
 
     //-------doubled pawns----------------------
@@ -359,7 +373,7 @@ pub fn evaluate_for_color(board: &Board, color: chess::Color) -> f32{
         king_safety_bonus = 1.2 * bb_pawns_in_front_of_king.count_ones() as f32;
     }
 
-    king_safety_bonus = king_safety_bonus * (1.0-eg_prob);//taper this off towards the end game
+    king_safety_bonus *= 1.0-eg_prob;//taper this off towards the end game
 
 
     //info!("Piece val: {piece_val}");
@@ -370,12 +384,13 @@ pub fn evaluate_for_color(board: &Board, color: chess::Color) -> f32{
 }
 
 
-pub fn evaluate(board: &Board, perspective: chess::Color) -> f32{
-    //returns how good a board is from the perspective of a certain player
+pub fn evaluate(board: &Board, perspective: chess::Color) -> Score{
+    //returns how good a board is from the perspective of a certain player, in centipawns
+    //the terms are all worked out in pawns, so scale at the very end
     
     let eval_me: f32 = evaluate_for_color(board, perspective);
     let eval_opp: f32 = evaluate_for_color(board, !perspective);
     
-    return eval_me - eval_opp;
+    return ((eval_me - eval_opp) * 100.0).round() as Score;
     
 }
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+use chess::{Board, BoardStatus, ChessMove, Color, MoveGen, Piece};
+use crate::zobristhasher::ZobristHasher;
+
+pub const STARTPOS: &str = "startpos";
+
+pub struct HistoryEntry{
+    pub board: Board,
+    pub hash: u64,
+    pub halfmove_clock: u32,//plies since the last capture or pawn move
+    pub chessmove: Option<ChessMove>//the move that got us here, None for the starting position
+}
+
+pub struct GameHistory{
+    start: String,//"startpos" or the fen the position command gave us
+    start_fullmove: u32,
+    entries: Vec<HistoryEntry>//oldest first, the first one is the start and every one after is one more move
+}
+
+impl GameHistory{
+    pub fn new(hasher: &ZobristHasher) -> Self{
+        return GameHistory::from_position(STARTPOS, Board::default(), 0, 1, hasher);
+    }
+
+    pub fn from_position(start: &str, board: Board, halfmove_clock: u32, fullmove: u32, hasher: &ZobristHasher) -> Self{
+        GameHistory {
+            start: start.to_owned(),
+            start_fullmove: fullmove.max(1),
+            entries: vec![HistoryEntry {
+                board: board,
+                hash: hasher.hash_board(&board),
+                halfmove_clock: halfmove_clock,
+                chessmove: None
+            }]
+        }
+    }
+
+    pub fn start(&self) -> &str{
+        return &self.start;
+    }
+
+    pub fn current(&self) -> &HistoryEntry{
+        return self.entries.last().expect("the history always has the starting position");
+    }
+
+    pub fn board(&self) -> Board{
+        return self.current().board;
+    }
+
+    pub fn halfmove_clock(&self) -> u32{
+        return self.current().halfmove_clock;
+    }
+
+    pub fn push_move(&mut self, chessmove: ChessMove, hasher: &ZobristHasher) -> bool{
+        //false (and nothing changes) if the move isn't legal here
+        let board = self.board();
+        if !board.legal(chessmove){
+            return false;
+        }
+        let irreversible = board.piece_on(chessmove.get_dest()).is_some()
+            || board.piece_on(chessmove.get_source()) == Some(Piece::Pawn);
+        let new_board = board.make_move_new(chessmove);
+        self.entries.push(HistoryEntry {
+            board: new_board,
+            hash: hasher.hash_board(&new_board),
+            halfmove_clock: if irreversible {0} else {self.halfmove_clock() + 1},
+            chessmove: Some(chessmove)
+        });
+        return true;
+    }
+
+    pub fn undo(&mut self) -> Option<ChessMove>{
+        //takes back the last move, the starting position can't be undone
+        if self.entries.len() <= 1{
+            return None;
+        }
+        return self.entries.pop().and_then(|e| e.chessmove);
+    }
+
+    pub fn moves(&self) -> Vec<ChessMove>{
+        return self.entries.iter().filter_map(|e| e.chessmove).collect();
+    }
+
+    pub fn previous_hashes(&self) -> Vec<u64>{
+        //keys of every position before the current one, what the search checks repetitions against
+        return self.entries[..self.entries.len()-1].iter().map(|e| e.hash).collect();
+    }
+
+    pub fn repetitions(&self) -> usize{
+        //how many times the current position has been on the board, this time included
+        //only positions since the last capture or pawn move can match
+        let current = self.current();
+        let lookback = (current.halfmove_clock as usize).min(self.entries.len() - 1);
+        let earlier = &self.entries[self.entries.len() - 1 - lookback..self.entries.len() - 1];
+        return 1 + earlier.iter().filter(|e| e.hash == current.hash).count();
+    }
+
+    pub fn result(&self) -> &'static str{
+        let board = self.board();
+        match board.status(){
+            BoardStatus::Checkmate => {
+                if board.side_to_move() == Color::White {return "0-1";} else {return "1-0";}
+            }
+            BoardStatus::Stalemate => return "1/2-1/2",
+            BoardStatus::Ongoing => {}
+        }
+        if self.halfmove_clock() >= 100 || self.repetitions() >= 3{
+            return "1/2-1/2";
+        }
+        return "*";
+    }
+
+    pub fn to_pgn(&self) -> String{
+        let result = self.result();
+        let mut pgn = String::new();
+        pgn.push_str("[Event \"?\"]\n[Site \"?\"]\n[Date \"????.??.??\"]\n[Round \"?\"]\n");
+        pgn.push_str("[White \"?\"]\n[Black \"?\"]\n");
+        pgn.push_str(&format!("[Result \"{result}\"]\n"));
+        if self.start != STARTPOS{
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", self.start));
+        }
+        pgn.push('\n');
+
+        //movetext, wrapped so no line goes past 80 characters
+        let mut tokens: Vec<String> = vec![];
+        let mut fullmove = self.start_fullmove;
+        for (idx, entry) in self.entries.iter().enumerate().skip(1){
+            let before = &self.entries[idx-1].board;
+            let chessmove = entry.chessmove.expect("every entry after the first has a move");
+            if before.side_to_move() == Color::White{
+                tokens.push(format!("{fullmove}."));
+            }else if idx == 1{
+                tokens.push(format!("{fullmove}..."));//the game started with black to move
+            }
+            tokens.push(san(before, chessmove));
+            if before.side_to_move() == Color::Black{
+                fullmove += 1;
+            }
+        }
+        tokens.push(result.to_owned());
+
+        let mut line = String::new();
+        for token in tokens{
+            if !line.is_empty() && line.len() + 1 + token.len() > 80{
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty(){
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        return pgn;
+    }
+}
+
+impl std::fmt::Display for GameHistory{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        //how often each position has come up, same layout the old game-state hash map printed
+        let mut counts: HashMap<u64,u8> = HashMap::new();
+        for e in &self.entries{
+            *counts.entry(e.hash).or_insert(0) += 1;
+        }
+        writeln!(f, "--Count-----Hash---------------------")?;
+        for (h,count) in &counts{
+            writeln!(f, "--{}-------- {:x}",count,h)?;
+        }
+        return Ok(());
+    }
+}
+
+fn piece_letter(piece: Piece) -> &'static str{
+    match piece{
+        Piece::Pawn => "",
+        Piece::Knight => "N",
+        Piece::Bishop => "B",
+        Piece::Rook => "R",
+        Piece::Queen => "Q",
+        Piece::King => "K"
+    }
+}
+
+pub fn san(board: &Board, chessmove: ChessMove) -> String{
+    //standard algebraic notation for a legal move, e.g. Nbd7, exd5, e8=Q+, O-O
+    let source = chessmove.get_source();
+    let dest = chessmove.get_dest();
+    let piece = board.piece_on(source).expect("there should always be a piece at the source of a move");
+    let file_char = |sq: chess::Square| (b'a' + sq.get_file().to_index() as u8) as char;
+    let rank_char = |sq: chess::Square| (b'1' + sq.get_rank().to_index() as u8) as char;
+
+    let mut s = String::new();
+    let file_distance = (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs();
+    if piece == Piece::King && file_distance == 2{
+        s.push_str(if dest.get_file() == chess::File::G {"O-O"} else {"O-O-O"});
+    }else{
+        let is_capture = board.piece_on(dest).is_some() || (piece == Piece::Pawn && file_distance != 0);
+        if piece == Piece::Pawn{
+            if is_capture{
+                s.push(file_char(source));
+            }
+        }else{
+            s.push_str(piece_letter(piece));
+            //another piece of the same kind that can go to the same square needs telling apart
+            let rivals: Vec<chess::Square> = MoveGen::new_legal(board)
+                .filter(|m| m.get_dest() == dest && m.get_source() != source && board.piece_on(m.get_source()) == Some(piece))
+                .map(|m| m.get_source())
+                .collect();
+            if !rivals.is_empty(){
+                if rivals.iter().all(|r| r.get_file() != source.get_file()){
+                    s.push(file_char(source));
+                }else if rivals.iter().all(|r| r.get_rank() != source.get_rank()){
+                    s.push(rank_char(source));
+                }else{
+                    s.push(file_char(source));
+                    s.push(rank_char(source));
+                }
+            }
+        }
+        if is_capture{
+            s.push('x');
+        }
+        s.push(file_char(dest));
+        s.push(rank_char(dest));
+        match chessmove.get_promotion(){
+            Some(p) => {
+                s.push('=');
+                s.push_str(piece_letter(p));
+            }
+            None => {}
+        }
+    }
+
+    let after = board.make_move_new(chessmove);
+    if after.status() == BoardStatus::Checkmate{
+        s.push('#');
+    }else if after.checkers().popcnt() > 0{
+        s.push('+');
+    }
+    return s;
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    fn history_after(moves: &[&str]) -> GameHistory{
+        let hasher = ZobristHasher::new();
+        let mut history = GameHistory::new(&hasher);
+        for m in moves{
+            assert!(history.push_move(ChessMove::from_str(m).unwrap(), &hasher), "{m} should be legal");
+        }
+        return history;
+    }
+
+    #[test]
+    fn san_pieces_captures_and_castling(){
+        let history = history_after(&["e2e4", "d7d5", "e4d5", "g8f6", "g1f3", "f6d5", "f1c4", "c8g4"]);
+        let pgn = history.to_pgn();
+        assert!(pgn.contains("1. e4 d5 2. exd5 Nf6 3. Nf3 Nxd5 4. Bc4 Bg4 *"), "{pgn}");
+
+        let board = history.board();
+        assert_eq!(san(&board, ChessMove::from_str("e1g1").unwrap()), "O-O");
+        assert_eq!(san(&board, ChessMove::from_str("c4b5").unwrap()), "Bb5+");
+    }
+
+    #[test]
+    fn san_disambiguation_and_promotion(){
+        let board = Board::from_str("4k3/1P6/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        assert_eq!(san(&board, ChessMove::from_str("a1d1").unwrap()), "Rad1");
+        assert_eq!(san(&board, ChessMove::from_str("h1d1").unwrap()), "Rhd1");
+        assert_eq!(san(&board, ChessMove::from_str("b7b8q").unwrap()), "b8=Q+");
+        assert_eq!(san(&board, ChessMove::from_str("b7b8n").unwrap()), "b8=N");
+    }
+
+    #[test]
+    fn undo_restores_the_position_and_clock(){
+        let mut history = history_after(&["g1f3", "g8f6"]);
+        assert_eq!(history.halfmove_clock(), 2);
+        assert_eq!(history.undo(), Some(ChessMove::from_str("g8f6").unwrap()));
+        assert_eq!(history.moves(), vec![ChessMove::from_str("g1f3").unwrap()]);
+        assert_eq!(history.halfmove_clock(), 1);
+        history.undo();
+        assert_eq!(history.board(), Board::default());
+        assert_eq!(history.undo(), None);
+    }
+
+    #[test]
+    fn threefold_repetition_is_a_draw(){
+        let history = history_after(&["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(history.repetitions(), 3);
+        assert_eq!(history.result(), "1/2-1/2");
+        assert_eq!(history.previous_hashes().len(), 8);
+    }
+
+    #[test]
+    fn illegal_moves_are_refused(){
+        let hasher = ZobristHasher::new();
+        let mut history = GameHistory::new(&hasher);
+        assert!(!history.push_move(ChessMove::from_str("e2e5").unwrap(), &hasher));
+        assert!(history.moves().is_empty());
+    }
+}
@@ -1,5 +1,8 @@
 
 
+//the house style: explicit returns, field: field in constructors, match even for one pattern
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::single_match)]
+
 use core::f32;
 use std::{fs, io::{self,BufRead,Write}, result};
 use rand::Rng;
@@ -9,6 +12,9 @@ use std::collections::BinaryHeap;
 use std::collections::HashMap;
 use std::fs::{File};
 use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
 
 mod evaluation;
 use evaluation::evaluate;
@@ -23,6 +29,23 @@ mod mcts;
 
 mod zobristhasher;
 
+mod engineoptions;
+use engineoptions::EngineOptions;
+
+mod transpositiontable;
+use transpositiontable::TranspositionTable;
+
+mod see;
+
+mod moveordering;
+use moveordering::OrderingTables;
+
+mod gamehistory;
+use gamehistory::GameHistory;
+
+mod timemanager;
+use timemanager::{PonderHit, TimeManager};
+
 
 
 use log::{debug,info,warn,error};
@@ -32,39 +55,85 @@ use crate::{mcts::{playout, pure_mcts_search}, zobristhasher::ZobristHasher};
 
 pub struct ChessEngine{
     nodes_visited: u64,
-    board: Board,
     hasher: ZobristHasher,
     opening_book: HashMap<u64,Vec<BookEntry>>,
-    in_book: bool
+    in_book: bool,
+    stop_flag: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<search::Searcher>>,
+    options: EngineOptions,
+    tt: Arc<TranspositionTable>,
+    ordering: OrderingTables,//killers and history, lent to each search and handed back when it finishes
+    history: GameHistory,//every position and move of the game so far, the current board is the last one
+    ponder_hit: Option<PonderHit>//set while a go ponder search is waiting to hear if the gui played our expected move
 }
 
 
 
 impl ChessEngine{
-    fn new() -> Self{
-        ChessEngine { 
-            board: Board::default(),
+    fn new(book_file: &str) -> Self{
+        let options = EngineOptions::new(book_file);
+        let tt = Arc::new(TranspositionTable::new(options.spin(engineoptions::HASH) as usize));
+        let hasher = ZobristHasher::new();
+        let mut engine = ChessEngine { 
             nodes_visited: 0,
-            hasher: ZobristHasher::new(),
-            opening_book: chessutil::load_book("C:/src/nullptr_bot/books/Titans.bin"),
-            in_book: true
+            history: GameHistory::new(&hasher),
+            hasher: hasher,
+            opening_book: HashMap::new(),
+            in_book: true,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            options: options,
+            tt: tt,
+            ordering: OrderingTables::default(),
+            ponder_hit: None
+        };
+        engine.load_opening_book();
+        return engine;
+    }
+
+    fn load_opening_book(&mut self){
+        //a missing or broken book just means we think from move 1
+        let book_file = self.options.string(engineoptions::BOOK_FILE);
+        if book_file.is_empty(){
+            info!("No book file set, playing without a book");
+            self.opening_book = HashMap::new();
+            self.in_book = false;
+            return;
+        }
+
+        match chessutil::load_book(&book_file, self.options.spin(engineoptions::BOOK_MIN_WEIGHT) as u16){
+            Ok(book) => {
+                info!("Loaded {} book positions from {}", book.len(), book_file);
+                self.opening_book = book;
+                self.in_book = true;
+            }
+            Err(e) => {
+                warn!("{e}, playing without a book");
+                self.opening_book = HashMap::new();
+                self.in_book = false;
+            }
         }
     }
 
     fn handle_searchbenchmark(&mut self, tokens: &[&str]){
+        self.wait_for_search();
         self.nodes_visited = 0;
+        self.stop_flag.store(false, Ordering::Relaxed);
         
-        let think_time: u32 = 1000000;
         let timer = std::time::Instant::now();
 
-        let my_color = self.board.side_to_move();
+        let my_color = self.history.board().side_to_move();
 
         let search_depth = tokens[1].parse().unwrap();
 
         
+        let mut searcher = search::Searcher::new(self.hasher.clone(), self.tt.clone(), self.stop_flag.clone());
+        searcher.pruning = self.pruning_params();
+        searcher.set_game_history(self.history.previous_hashes(), self.history.halfmove_clock());
+        self.tt.new_search();
         let (eval,chessmove) = search::iterative_deepening_search_with_time(
-            self, self.board, search_depth,
-            5.0, think_time,self.board.side_to_move(), true);
+            &mut searcher, self.history.board(), search_depth, &mut TimeManager::infinite());
+        self.nodes_visited = searcher.nodes_visited;
         
 
         let elapsed = timer.elapsed().as_millis();
@@ -74,24 +143,48 @@ impl ChessEngine{
 
         let visited = self.nodes_visited;
         println!("Evaluated {visited} leafs");
+        println!("{}", searcher.stats);
         io::stdout().flush().unwrap();
 
     }
 
+    fn pruning_params(&self) -> search::PruningParams{
+        search::PruningParams {
+            reverse_futility_margin: self.options.spin(engineoptions::REVERSE_FUTILITY_MARGIN) as evaluation::Score,
+            futility_margin: self.options.spin(engineoptions::FUTILITY_MARGIN) as evaluation::Score,
+            razor_margin: self.options.spin(engineoptions::RAZOR_MARGIN) as evaluation::Score
+        }
+    }
+
     fn handle_clearhash(&mut self){
-        self.hasher.gamestate_hashmap = HashMap::new();
-        println!("Cleared game-state hash map");
+        self.wait_for_search();
+        //the game history stays, it holds the fifty move clock and the moves for the pgn
+        self.tt.clear();
+        println!("Cleared transposition table");
         io::stdout().flush().unwrap();
     }
 
+    fn handle_ucinewgame(&mut self){
+        //nothing from the last game should leak into this one: repetitions, book state, tables, history
+        //a search still running (pondering most likely) is stopped first, which also drops the ponder state
+        //mcts builds its tree fresh on every call so there is nothing kept for it here
+        self.handle_stop();
+        self.history = GameHistory::new(&self.hasher);
+        self.in_book = !self.opening_book.is_empty();
+        self.tt.clear();
+        self.ordering.clear();
+        self.nodes_visited = 0;
+    }
+
     fn handle_hashstatus(&mut self){
-        let hs: String = self.hasher.to_string();
+        let hs: String = self.history.to_string();
         print!("{hs}");
+        print!("{}", self.tt);
         io::stdout().flush().unwrap();
     }
 
     fn handle_hashme(&mut self){
-        let h = self.hasher.hash_board(&self.board);
+        let h = self.history.current().hash;
         println!("{:x}",h);
         io::stdout().flush().unwrap();
     }
@@ -105,9 +198,9 @@ impl ChessEngine{
     }
 
     fn handle_evaluate(&mut self){
-        let c = self.board.side_to_move();
-        let eval = evaluate(&self.board, c);
-        println!("Evaluation: {eval}");
+        let board = self.history.board();
+        let eval = evaluate(&board, board.side_to_move());
+        println!("Evaluation: {eval} cp");
         io::stdout().flush().unwrap();
 
     }
@@ -115,11 +208,47 @@ impl ChessEngine{
     fn handle_uci(&mut self){
         println!("id name nullptrbot");
         println!("id author alex");
+        for option_line in self.options.uci_lines(){
+            println!("{option_line}");
+        }
         println!("uciok");
         io::stdout().flush().unwrap();
 
     }
 
+    fn handle_setoption(&mut self, tokens: &[&str]){
+        //setoption name <name, can have spaces> [value <value, can have spaces>]
+        self.wait_for_search();
+        let name_index = match tokens.iter().position(|&x| x == "name"){
+            Some(idx) => idx,
+            None => return
+        };
+        let value_index = tokens.iter().position(|&x| x == "value").unwrap_or(tokens.len());
+        if value_index <= name_index{
+            return;
+        }
+        let name = tokens[name_index+1..value_index].join(" ");
+        let value = if value_index < tokens.len() {tokens[value_index+1..].join(" ")} else {String::new()};
+
+        match self.options.set(&name, &value){
+            Ok(changed) => {
+                if changed == engineoptions::BOOK_FILE || changed == engineoptions::BOOK_MIN_WEIGHT{
+                    self.load_opening_book();
+                }
+                if changed == engineoptions::HASH{
+                    //drop the old table before allocating the new one so we don't need both at once
+                    self.tt = Arc::new(TranspositionTable::new(1));
+                    self.tt = Arc::new(TranspositionTable::new(self.options.spin(engineoptions::HASH) as usize));
+                }
+            }
+            Err(e) => {
+                warn!("setoption failed: {e}");
+                println!("info string {e}");
+                io::stdout().flush().unwrap();
+            }
+        }
+    }
+
     fn handle_isready(&mut self){
         println!("readyok");
         io::stdout().flush().unwrap();
@@ -127,48 +256,83 @@ impl ChessEngine{
     }
 
     fn handle_position(&mut self, tokens: &[&str]){
+        self.wait_for_search();
         if tokens.len() < 2{
             return;
         }
-        if tokens[1] == "startpos"{
-            self.board = Board::default();
-            self.hasher.insert_board(&self.board);
-        }
-        else if tokens[1] == "fen"{
-            if tokens.len() < 8{
-                return;
-            }
-            let fen_parts: Vec<&str> = tokens[2..8].to_vec();
-            let fen_str:String = fen_parts.join(" ");
+        let moves_index = tokens.iter().position(|&x| x == "moves").unwrap_or(tokens.len());
 
-            match Board::from_str(&fen_str){
-                Ok(board) => {
-                    self.board = board;
-                    self.hasher.insert_board(&board);
+        let (start, board, halfmove_clock, fullmove) = 
+            if tokens[1] == "startpos"{
+                (gamehistory::STARTPOS.to_owned(), Board::default(), 0, 1)
+            }
+            else if tokens[1] == "fen"{
+                if tokens.len() < 8{
+                    return;
                 }
-                Err(_) => {
-                    self.board = Board::default();
+                let fen_parts: Vec<&str> = tokens[2..8].to_vec();
+                let fen_str:String = fen_parts.join(" ");
+
+                match Board::from_str(&fen_str){
+                    Ok(board) => (fen_str, board, tokens[6].parse().unwrap_or(0), tokens[7].parse().unwrap_or(1)),
+                    Err(_) => {
+                        warn!("bad fen {fen_str}, using the start position");
+                        (gamehistory::STARTPOS.to_owned(), Board::default(), 0, 1)
+                    }
                 }
             }
+            else{
+                return;
+            };
+
+        let moves: Vec<ChessMove> = tokens[(moves_index + 1).min(tokens.len())..].iter()
+            .filter_map(|move_str| ChessMove::from_str(move_str).ok())
+            .collect();
+
+        //guis send the whole game every move, usually it is just the last position plus a move or two
+        //so if it is the same game with more moves on the end only those get played, otherwise start over
+        let played = self.history.moves();
+        let same_game = self.history.start() == start && moves.len() >= played.len() && moves[..played.len()] == played[..];
+        let new_moves = if same_game {&moves[played.len()..]} else {&moves[..]};
+        if !same_game{
+            self.history = GameHistory::from_position(&start, board, halfmove_clock, fullmove, &self.hasher);
         }
 
-        if let Some(moves_index) = tokens.iter().position(|&x| x == "moves") {
-            for &move_str in &tokens[moves_index + 1..] {
-                if let Ok(chess_move) = ChessMove::from_str(move_str) {
-                    self.board = self.board.make_move_new(chess_move);
-                    self.hasher.insert_board(&self.board);
-                }
+        for &chess_move in new_moves{
+            if !self.history.push_move(chess_move, &self.hasher){
+                warn!("illegal move {chess_move} in position command, ignoring the rest");
+                break;
             }
         }
     }
 
+    fn handle_undo(&mut self){
+        self.wait_for_search();
+        match self.history.undo(){
+            Some(m) => println!("Took back {m}"),
+            None => println!("Nothing to undo")
+        }
+        io::stdout().flush().unwrap();
+    }
+
+    fn handle_moves(&mut self){
+        let moves: Vec<String> = self.history.moves().iter().map(|m| m.to_string()).collect();
+        println!("{}", moves.join(" "));
+        io::stdout().flush().unwrap();
+    }
+
+    fn handle_pgn(&mut self){
+        print!("{}", self.history.to_pgn());
+        io::stdout().flush().unwrap();
+    }
+
     fn handle_mctssearch(&mut self, tokens: &[&str]){
         let playout_depth: i32 = tokens[1].parse().unwrap();
-        pure_mcts_search(self.board, playout_depth);
+        pure_mcts_search(self.history.board(), playout_depth);
     }
 
     fn handle_playout(&mut self){
-        playout(self.board);
+        playout(self.history.board());
     }
 
     /*
@@ -398,40 +562,46 @@ impl ChessEngine{
 
     */
 
-    fn calculate_think_time_ms(&mut self, go_command: &UCIGoCommand, my_color: chess::Color) -> i32{
-        //go command is in ms        
+    fn make_time_manager(&self, go_command: &UCIGoCommand, my_color: chess::Color) -> TimeManager{
+        //go command is in ms
+        let move_overhead = self.options.spin(engineoptions::MOVE_OVERHEAD) as u64;//time lost talking to the gui
         if let Some(movetime) = go_command.movetime{
-            return movetime;
+            return TimeManager::fixed(movetime.max(0) as u64, move_overhead);
+        }
+        if go_command.infinite.unwrap_or(false){
+            return TimeManager::infinite();//only a stop command ends the search
         }
-        else{
-            if go_command.infinite.unwrap_or(false){
-                return 100000000;
-            }
-            
-            let (my_time,my_inc) = 
-                if self.board.side_to_move() == chess::Color::White{
-                    (go_command.wtime,go_command.winc)
-                }else{
-                    (go_command.btime,go_command.binc)
-                };
-
-            let base_time = my_time.unwrap_or(30000);
-            let increment = my_inc.unwrap_or(0);  
-
-           
-            //let egp = early_game_probability(&self.board);
 
-            let proportion_of_game = 1.0/20.0;
-            let time_value = ((base_time as f32)*proportion_of_game) as i32 + (increment/2);
+        let (my_time,my_inc) = 
+            if my_color == chess::Color::White{
+                (go_command.wtime,go_command.winc)
+            }else{
+                (go_command.btime,go_command.binc)
+            };
 
-            
-            return time_value;
+        let has_other_limit = go_command.depth.is_some() || go_command.nodes.is_some() || go_command.mate.is_some();
+        match my_time{
+            Some(t) => {
+                return TimeManager::from_clock(t.max(0) as u64, my_inc.unwrap_or(0).max(0) as u64,
+                    go_command.movestogo.map(|m| m.max(1) as u64), move_overhead,
+                    self.options.spin(engineoptions::TIME_DIVISOR) as u64,
+                    self.options.spin(engineoptions::BASE_TIME_SCALE) as f32);
+            }
+            None => {
+                if has_other_limit{
+                    return TimeManager::infinite();//go depth/nodes/mate without a clock, let that limit end the search
+                }
+                //a bare go, pretend we have 30 seconds like before
+                return TimeManager::from_clock(30000, 0, None, move_overhead,
+                    self.options.spin(engineoptions::TIME_DIVISOR) as u64,
+                    self.options.spin(engineoptions::BASE_TIME_SCALE) as f32);
+            }
         }
     }
 
 
     fn get_book_move(&mut self) -> Option<ChessMove>{
-        let h = self.hasher.hash_board(&self.board);
+        let h = self.history.current().hash;
         match self.opening_book.get(&h){
             Some(v_be) => {
                 info!("Book move!");
@@ -448,40 +618,136 @@ impl ChessEngine{
         return None;
     }
 
-    fn generate_move(&mut self, _think_time_ms: i32, my_color: chess::Color) -> Option<ChessMove>{ 
-        if self.in_book{
+    fn generate_move(&mut self, mut time_manager: TimeManager, max_depth: usize, limits: search::SearchLimits, my_color: chess::Color, wait_for_stop: bool){ 
+        //the search runs on its own thread so the uci loop can still hear stop/quit/isready
+        //the thread prints bestmove itself when it is done, and hands its searcher back when joined
+        let mut searcher = search::Searcher::new(self.hasher.clone(), self.tt.clone(), self.stop_flag.clone());
+        searcher.limits = limits;
+        searcher.set_game_history(self.history.previous_hashes(), self.history.halfmove_clock());
+        searcher.pruning = self.pruning_params();
+        searcher.ordering = std::mem::take(&mut self.ordering);
+        searcher.multi_pv = self.options.spin(engineoptions::MULTI_PV) as usize;//helpers only ever look for the best move
+        let board = self.history.board();
+        self.tt.new_search();
+
+        //lazy smp: the other threads search the same position into the shared table and we ignore their answers
+        //each one has its own killers, history and position stack, only the table and node count are shared
+        //they have their own stop flag so the main thread can end them once it has its move
+        let helper_stop = Arc::new(AtomicBool::new(false));
+        let mut helpers: Vec<search::Searcher> = vec![];
+        for thread_id in 1..self.options.spin(engineoptions::THREADS) as usize{
+            let mut helper = search::Searcher::new(self.hasher.clone(), self.tt.clone(), helper_stop.clone());
+            helper.thread_id = thread_id;
+            helper.shared_nodes = searcher.shared_nodes.clone();
+            helper.limits = searcher.limits.clone();
+            helper.set_game_history(self.history.previous_hashes(), self.history.halfmove_clock());
+            helper.pruning = self.pruning_params();
+            helpers.push(helper);
+        }
+
+        let handle = std::thread::spawn(move || {
+            let helper_handles: Vec<JoinHandle<search::Searcher>> = helpers.into_iter().map(|mut helper| {
+                std::thread::spawn(move || {
+                    search::iterative_deepening_search_with_time(&mut helper, board, max_depth, &mut TimeManager::infinite());
+                    return helper;
+                })
+            }).collect();
+
+            let (eval,chessmove) = search::iterative_deepening_search_with_time(&mut searcher, board, max_depth, &mut time_manager);
+
+            //go infinite is not allowed to send bestmove before the gui says stop, go ponder not before ponderhit or stop
+            while !searcher.stop.load(Ordering::Relaxed) && (wait_for_stop || time_manager.is_pondering()){
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+
+            helper_stop.store(true, Ordering::Relaxed);
+            for helper_handle in helper_handles{
+                let helper = helper_handle.join().expect("helper search thread panicked");
+                searcher.nodes_visited += helper.nodes_visited;
+            }
+
+            let ponder_move = search::ponder_move(&searcher, &board, chessmove);
+            print_bestmove(chessmove, ponder_move);
+            return searcher;
+        });
+        self.search_thread = Some(handle);
+    }
+
+    fn handle_go(&mut self, tokens: &[&str]) {
+        self.wait_for_search();
+        self.nodes_visited = 0;
+        self.stop_flag.store(false, Ordering::Relaxed);
+        let my_color: chess::Color = self.history.board().side_to_move();
+        let go_command: UCIGoCommand = UCIGoCommand::new(tokens);
+        let infinite = go_command.infinite.unwrap_or(false);
+        let ponder = go_command.ponder.unwrap_or(false);
+        let analysis_limits = go_command.depth.is_some() || go_command.nodes.is_some() 
+                            || go_command.mate.is_some() || go_command.searchmoves.is_some();
+
+        let use_book = self.options.check(engineoptions::OWN_BOOK);
+        if use_book && self.in_book && !infinite && !ponder && !analysis_limits{
             match self.get_book_move(){
                 Some(cm) => {
-                    return Some(cm);
+                    print_bestmove(Some(cm), None);
+                    return;
                 }
                 None => {}
             }
         }
+
+        let mut time_manager = self.make_time_manager(&go_command, my_color);
+        if ponder{
+            //the position already has the move we expect them to play, think on it until they do
+            self.ponder_hit = Some(time_manager.ponder());
+        }
+        info!("think for {} ms, at most {} ms", time_manager.soft_limit_ms(), time_manager.hard_limit_ms());
+
+        //the depth loop is exclusive of max_depth, so go depth N needs N+1
+        let max_depth = match (go_command.depth, go_command.mate){
+            (Some(d), _) => (d.max(1) as usize + 1).min(search::MAX_SEARCH_DEPTH),
+            (None, Some(m)) => (2 * m.max(1) as usize + 1).min(search::MAX_SEARCH_DEPTH),//a mate in N is at most 2N-1 plies away
+            (None, None) => {
+                if infinite || go_command.nodes.is_some(){
+                    search::MAX_SEARCH_DEPTH
+                }else{
+                    self.options.spin(engineoptions::MAX_DEPTH) as usize + 1
+                }
+            }
+        };
+
+        let limits = search::SearchLimits{
+            nodes: go_command.nodes,
+            mate: go_command.mate.map(|m| m.max(1) as usize),
+            searchmoves: go_command.searchmoves
+        };
         
-        let (eval,chessmove) = search::iterative_deepening_search_with_time(self, self.board, 12, 5.0, _think_time_ms as u32, my_color, true);
-        
-        return chessmove;
-        //return self.iterative_deepening_search(_think_time_ms as u128, 12, my_color)
+        self.generate_move(time_manager, max_depth, limits, my_color, infinite);
     }
 
-    fn handle_go(&mut self, tokens: &[&str]) {
-        self.nodes_visited = 0;
-        let my_color: chess::Color = self.board.side_to_move();
-        let go_command: UCIGoCommand = UCIGoCommand::new(tokens);
+    fn handle_ponderhit(&mut self){
+        //they played the move we were pondering, the clock starts now and the search carries on
+        match self.ponder_hit.take(){
+            Some(ponder_hit) => ponder_hit.hit(),
+            None => {}
+        }
+    }
 
-        let think_time_ms: i32 = self.calculate_think_time_ms(&go_command, my_color);
-        info!("think for {} ms",think_time_ms);
-        
+    fn handle_stop(&mut self){
+        self.stop_flag.store(true, Ordering::Relaxed);
+        self.wait_for_search();
+    }
 
-        if let Some(best_move) = self.generate_move(think_time_ms, my_color) {
-            println!("bestmove {}", best_move);
-            
-        } else {
-            // No legal moves (checkmate or stalemate)
-            println!("bestmove 0000");
+    fn wait_for_search(&mut self){
+        //blocks until the running search (if any) has printed its bestmove
+        match self.search_thread.take(){
+            Some(handle) => {
+                let searcher = handle.join().expect("search thread panicked");
+                self.nodes_visited = searcher.nodes_visited;
+                self.ordering = searcher.ordering;
+            }
+            None => {}
         }
-        io::stdout().flush().unwrap();
-        
+        self.ponder_hit = None;
     }
 
     fn run(&mut self) {
@@ -502,13 +768,20 @@ impl ChessEngine{
             match tokens[0] {
                 "uci" => self.handle_uci(),
                 "isready" => self.handle_isready(),
+                "setoption" => self.handle_setoption(&tokens),
+                "ucinewgame" => self.handle_ucinewgame(),
                 "position" => self.handle_position(&tokens),
                 "go" => self.handle_go(&tokens),
+                "ponderhit" => self.handle_ponderhit(),
+                "stop" => self.handle_stop(),
                 "searchbenchmark" => self.handle_searchbenchmark(&tokens),
                 "evaluate" => self.handle_evaluate(),
                 "clearhash" => self.handle_clearhash(),
                 "hashstatus" => self.handle_hashstatus(),
                 "hashme" => self.handle_hashme(),
+                "undo" => self.handle_undo(),
+                "moves" => self.handle_moves(),
+                "pgn" => self.handle_pgn(),
                 "booktrue" => self.handle_booktrue(),
                 "bookfalse" => self.handle_bookfalse(),
                 "playout" => self.handle_playout(),
@@ -517,17 +790,61 @@ impl ChessEngine{
                 _ => {} // Ignore unknown commands
             }
         }
+
+        self.handle_stop();//don't leave a search thread running on quit
     }
 
 }
 
+fn print_bestmove(chessmove: Option<ChessMove>, ponder_move: Option<ChessMove>){
+    match (chessmove, ponder_move){
+        (Some(cm), Some(pm)) => {
+            println!("bestmove {} ponder {}", cm, pm);
+        }
+        (Some(cm), None) => {
+            println!("bestmove {}", cm);
+        }
+        (None, _) => {
+            // No legal moves (checkmate or stalemate)
+            println!("bestmove 0000");
+        }
+    }
+    io::stdout().flush().unwrap();
+}
+
+fn find_book_file(args: &[String]) -> String{
+    //--book <path> on the command line, then NULLPTR_BOT_BOOK, then books/Titans.bin next to the binary
+    //(or in the working directory, which is where run.bat starts us from)
+    if let Some(idx) = args.iter().position(|a| a == "--book") && let Some(path) = args.get(idx+1){
+        return path.clone();
+    }
+    if let Some(path) = args.iter().find_map(|a| a.strip_prefix("--book=")){
+        return path.to_owned();
+    }
+
+    if let Ok(path) = std::env::var("NULLPTR_BOT_BOOK") && !path.is_empty(){
+        return path;
+    }
+
+    let relative_book = std::path::Path::new("books").join("Titans.bin");
+    if let Ok(exe) = std::env::current_exe() && let Some(exe_dir) = exe.parent(){
+        let next_to_exe = exe_dir.join(&relative_book);
+        if next_to_exe.exists(){
+            return next_to_exe.to_string_lossy().into_owned();
+        }
+    }
+    return relative_book.to_string_lossy().into_owned();
+}
+
 fn main(){
     env_logger::Builder::from_default_env()
     .target(env_logger::Target::Stderr)
     .init();
 
+    let args: Vec<String> = std::env::args().collect();
+    let book_file = find_book_file(&args);
 
-    let mut engine = ChessEngine::new();
+    let mut engine = ChessEngine::new(&book_file);
 
 
 
@@ -38,7 +38,7 @@ pub fn mcts_selection_expansion(root: &mut GametreeNode) -> &GametreeNode{
         return root;
     }else{
         //if there is no children list, generate it
-        if(root.children.len() == 0){
+        if(root.children.is_empty()){
             let mg_moves = MoveGen::new_legal(&root.board);
             for chessmove in mg_moves{
                 let b2 = root.board.make_move_new(chessmove);
@@ -73,7 +73,7 @@ pub fn pure_mcts_search(board: chess::Board, playout_depth: i32){
 }
 
 pub fn playout(board: chess::Board){
-    let mut b2 = board.clone();
+    let mut b2 = board;
     
     let mut rng = rand::thread_rng();
 
@@ -85,7 +85,7 @@ pub fn playout(board: chess::Board){
         let rand_idx = rng.gen_range(0..num_moves);
 
         let m = movegen[rand_idx];
-        let mut b2copy = b2.clone();
+        let mut b2copy = b2;
         b2.make_move(m, &mut b2copy);
         b2 = b2copy;
 
@@ -0,0 +1,154 @@
+use chess::{ChessMove, Color};
+use crate::search::MAX_PLY;
+
+pub const MAX_HISTORY: i32 = 16384;//history scores stay inside +-this
+
+pub struct OrderingTables{
+    killers: [[Option<ChessMove>; 2]; MAX_PLY],//last two quiet moves that cut off at each ply, they often work in sibling positions too
+    history: Box<[[[i32; 64]; 64]; 2]>//[side][from][to], how often a quiet move has cut off, weighted by depth
+}
+
+impl Default for OrderingTables{
+    fn default() -> Self{
+        OrderingTables {
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2])
+        }
+    }
+}
+
+impl OrderingTables{
+    pub fn is_killer(&self, ply: usize, chessmove: ChessMove) -> Option<usize>{
+        //which killer slot the move is in, 0 is the most recent
+        if ply >= MAX_PLY{
+            return None;
+        }
+        return self.killers[ply].iter().position(|&k| k == Some(chessmove));
+    }
+
+    pub fn history(&self, side: Color, chessmove: ChessMove) -> i32{
+        return self.history[side.to_index()][chessmove.get_source().to_index()][chessmove.get_dest().to_index()];
+    }
+
+    fn add_history(&mut self, side: Color, chessmove: ChessMove, bonus: i32){
+        //gravity: the closer an entry is to the cap the less it moves, so nothing runs away
+        let entry = &mut self.history[side.to_index()][chessmove.get_source().to_index()][chessmove.get_dest().to_index()];
+        let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+
+    pub fn update_on_cutoff(&mut self, side: Color, ply: usize, depth: usize, cutoff_move: ChessMove, tried_quiets: &[ChessMove]){
+        //cutoff_move is a quiet move that just failed high, tried_quiets are the quiets searched before it that didn't
+        if ply < MAX_PLY && self.killers[ply][0] != Some(cutoff_move){
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(cutoff_move);
+        }
+
+        let bonus = (depth * depth) as i32;
+        self.add_history(side, cutoff_move, bonus);
+        for &m in tried_quiets{
+            self.add_history(side, m, -bonus);
+        }
+    }
+
+    pub fn new_search(&mut self){
+        //killers are tied to plies from the old root so they mean nothing now
+        //history is still roughly right, just trust it less
+        self.killers = [[None; 2]; MAX_PLY];
+        for side in self.history.iter_mut(){
+            for from in side.iter_mut(){
+                for entry in from.iter_mut(){
+                    *entry /= 2;
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self){
+        *self = OrderingTables::default();
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    fn mv(uci: &str) -> ChessMove{
+        return ChessMove::from_str(uci).expect("test moves should parse");
+    }
+
+    #[test]
+    fn killers_shift_down_without_duplicates(){
+        let mut tables = OrderingTables::default();
+        tables.update_on_cutoff(Color::White, 3, 4, mv("g1f3"), &[]);
+        assert_eq!(tables.is_killer(3, mv("g1f3")), Some(0));
+
+        tables.update_on_cutoff(Color::White, 3, 4, mv("b1c3"), &[]);
+        assert_eq!(tables.is_killer(3, mv("b1c3")), Some(0));
+        assert_eq!(tables.is_killer(3, mv("g1f3")), Some(1));
+
+        //the same killer again must not push the other one out
+        tables.update_on_cutoff(Color::White, 3, 4, mv("b1c3"), &[]);
+        assert_eq!(tables.is_killer(3, mv("b1c3")), Some(0));
+        assert_eq!(tables.is_killer(3, mv("g1f3")), Some(1));
+
+        tables.update_on_cutoff(Color::White, 3, 4, mv("e2e4"), &[]);
+        assert_eq!(tables.is_killer(3, mv("e2e4")), Some(0));
+        assert_eq!(tables.is_killer(3, mv("b1c3")), Some(1));
+        assert_eq!(tables.is_killer(3, mv("g1f3")), None);
+
+        //killers are per ply
+        assert_eq!(tables.is_killer(4, mv("e2e4")), None);
+        assert_eq!(tables.is_killer(MAX_PLY, mv("e2e4")), None);
+    }
+
+    #[test]
+    fn history_rewards_the_cutoff_and_punishes_the_rest(){
+        let mut tables = OrderingTables::default();
+        tables.update_on_cutoff(Color::Black, 0, 5, mv("g8f6"), &[mv("a7a6"), mv("h7h6")]);
+        assert_eq!(tables.history(Color::Black, mv("g8f6")), 25);
+        assert_eq!(tables.history(Color::Black, mv("a7a6")), -25);
+        assert_eq!(tables.history(Color::Black, mv("h7h6")), -25);
+        //side to move is part of the key
+        assert_eq!(tables.history(Color::White, mv("g8f6")), 0);
+    }
+
+    #[test]
+    fn history_saturates_at_the_cap(){
+        let mut tables = OrderingTables::default();
+        for _ in 0..1000{
+            tables.update_on_cutoff(Color::White, 0, 40, mv("e2e4"), &[mv("d2d4")]);
+        }
+        let good = tables.history(Color::White, mv("e2e4"));
+        let bad = tables.history(Color::White, mv("d2d4"));
+        assert!((MAX_HISTORY / 2..=MAX_HISTORY).contains(&good), "{good}");
+        assert!((-MAX_HISTORY..=-MAX_HISTORY / 2).contains(&bad), "{bad}");
+
+        //a huge bonus is clamped instead of overshooting
+        tables.update_on_cutoff(Color::White, 0, 1000, mv("e2e4"), &[]);
+        assert!(tables.history(Color::White, mv("e2e4")) <= MAX_HISTORY);
+    }
+
+    #[test]
+    fn new_search_drops_killers_and_halves_history(){
+        let mut tables = OrderingTables::default();
+        tables.update_on_cutoff(Color::White, 2, 10, mv("g1f3"), &[]);
+        let before = tables.history(Color::White, mv("g1f3"));
+        tables.new_search();
+        assert_eq!(tables.is_killer(2, mv("g1f3")), None);
+        assert_eq!(tables.history(Color::White, mv("g1f3")), before / 2);
+    }
+
+    #[test]
+    fn clear_forgets_everything(){
+        //what ucinewgame does
+        let mut tables = OrderingTables::default();
+        tables.update_on_cutoff(Color::White, 2, 10, mv("g1f3"), &[mv("a2a3")]);
+        tables.clear();
+        assert_eq!(tables.is_killer(2, mv("g1f3")), None);
+        assert_eq!(tables.history(Color::White, mv("g1f3")), 0);
+        assert_eq!(tables.history(Color::White, mv("a2a3")), 0);
+    }
+}
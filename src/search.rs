@@ -1,217 +1,409 @@
 
 
-use chess::{BoardStatus, ChessMove};
+use chess::{BoardStatus, ChessMove, MoveGen, Piece};
 
-use crate::{chessutil, evaluation::evaluate, ChessEngine};
+use std::io::{self,Write};
+use std::sync::{Arc, LazyLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use crate::{chessutil, evaluation::{evaluate, piece_value, Score}, zobristhasher::ZobristHasher};
+use crate::transpositiontable::{Bound, TranspositionTable};
+use crate::see::see_ge;
+use crate::moveordering::OrderingTables;
+use crate::timemanager::TimeManager;
 
 use log::{debug,info,warn,error};
 
 
-pub fn search_alpha_beta(engine: &mut ChessEngine, board: chess::Board, depth: usize, mut alpha: f32, mut beta: f32, 
-                        my_color: chess::Color, my_move: bool, movelist: Option<Vec<ChessMove>>,
-                        timer: Option<&std::time::Instant>, time_limit: u32
-                        )
-                         -> (f32, Option<ChessMove>,bool){
-    //can take a pre-supplied move list
-    const MATE_VALUE: f32 = 100000.0;
+pub const MAX_SEARCH_DEPTH: usize = 64;
+pub const MAX_PLY: usize = 128;//iterations are capped at MAX_SEARCH_DEPTH but lines can run longer than that
 
+//all scores are centipawns from the side to move
+pub const DRAW_SCORE: Score = 0;
+pub const MATE_VALUE: Score = 30000;
+pub const MATE_BOUND: Score = MATE_VALUE - MAX_PLY as Score;//anything bigger than this is a mate
+pub const INFINITE_SCORE: Score = 32000;
 
-    if depth == 0{
-        let eval = evaluate(&board, my_color);
-        engine.nodes_visited += 1;
-        return (eval,None,true);
-    }
+const NODE_FLUSH_INTERVAL: u64 = 1024;
 
-    let mut moves: Vec<ChessMove> = vec![];
-    match movelist{
-        Some(ml) => {
-            moves = ml;
-        }
-        None => {
-            moves = chessutil::output_sorted_move_list(&board);
+const QS_DELTA_MARGIN: Score = 200;//biggest positional swing we expect a single capture to make
+
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+const NULL_MOVE_VERIFY_DEPTH: usize = 10;//from here on a null move cutoff has to be confirmed by a normal search
+
+const REVERSE_FUTILITY_MAX_DEPTH: usize = 6;
+const RAZOR_MAX_DEPTH: usize = 2;
+const FUTILITY_MAX_DEPTH: usize = 3;
+
+const SINGULAR_MIN_DEPTH: usize = 8;
+
+const LMR_MIN_DEPTH: usize = 3;
+const LMR_MIN_MOVES: usize = 3;//the first few moves always get searched at full depth
+const LMP_MAX_DEPTH: usize = 3;
+
+static LMR_TABLE: LazyLock<[[usize; 64]; 64]> = LazyLock::new(|| {
+    //reduction[depth][move number], grows with the log of both
+    let mut table = [[0; 64]; 64];
+    for (depth, row) in table.iter_mut().enumerate().skip(1){
+        for (move_number, r) in row.iter_mut().enumerate().skip(1){
+            *r = (0.75 + (depth as f32).ln() * (move_number as f32).ln() / 2.25) as usize;
         }
     }
-    
-    let num_moves = moves.len();
-    //info!("{num_moves} moves!!!!!!");
+    table
+});
+
+fn lmr_reduction(depth: usize, move_index: usize) -> usize{
+    return LMR_TABLE[depth.min(63)][(move_index + 1).min(63)];
+}
+
+const ASPIRATION_MIN_DEPTH: usize = 4;//shallow scores jump around too much to aim at
+const ASPIRATION_WINDOW: Score = 25;
+const ASPIRATION_GIVE_UP: Score = 1000;//past this just search the full window
+
+#[derive(Default, Debug)]
+pub struct SearchStats{
+    //how often the speculative parts of the search had to be redone, to see if they are paying off
+    pub null_move_cutoffs: u64,
+    pub lmr_searches: u64,
+    pub lmr_researches: u64,
+    pub pvs_researches: u64,
+    pub lmp_pruned: u64,
+    pub reverse_futility_cutoffs: u64,
+    pub razor_cutoffs: u64,
+    pub futility_pruned: u64,
+    pub check_extensions: u64,
+    pub singular_extensions: u64
+}
 
-    //if this is a 3fold repetition return stalemate
-    //not sure the logic is right tho
-    let state_hash = engine.hasher.hash_board(&board);
-    if engine.hasher.check_table(state_hash) == 2{
-        return (0.0,None,true);
+impl std::fmt::Display for SearchStats{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        let lmr_rate = if self.lmr_searches > 0 {100.0 * self.lmr_researches as f64 / self.lmr_searches as f64} else {0.0};
+        return write!(f, "null cutoffs {} lmr {} lmr re-searches {} ({:.1}%) pvs re-searches {} lmp pruned {} rfp cutoffs {} razor cutoffs {} futility pruned {} check extensions {} singular extensions {}",
+                        self.null_move_cutoffs, self.lmr_searches, self.lmr_researches, lmr_rate, self.pvs_researches, self.lmp_pruned,
+                        self.reverse_futility_cutoffs, self.razor_cutoffs, self.futility_pruned, self.check_extensions, self.singular_extensions);
     }
+}
 
-    if moves.is_empty(){
-        match board.status(){
-            BoardStatus::Stalemate => {
-                return (0.0,None,true);
-            }
-            BoardStatus::Checkmate => {
-                if board.side_to_move() == my_color{
-                    let eval = -MATE_VALUE - (depth as f32);
-                    return (eval,None,true)
-                }else{
-                    let eval = MATE_VALUE + (depth as f32);
-                    return (eval,None,true)
-                }
-            }
-            BoardStatus::Ongoing => {}
-            _ => {}
+pub struct PruningParams{
+    //margins per ply of depth left, set from the engine options so they can be tuned without a rebuild
+    pub reverse_futility_margin: Score,
+    pub futility_margin: Score,
+    pub razor_margin: Score
+}
+
+impl Default for PruningParams{
+    fn default() -> Self{
+        PruningParams {
+            reverse_futility_margin: 80,
+            futility_margin: 100,
+            razor_margin: 250
         }
     }
+}
 
-    
-    if my_move{
-        let mut max_eval: f32 = -MATE_VALUE;
-        let mut best_move: Option<ChessMove> = None;
+#[derive(Default, Clone)]
+pub struct SearchLimits{
+    //limits from the go command on top of the depth and time limits
+    pub nodes: Option<u64>,
+    pub mate: Option<usize>,//in moves, not plies
+    pub searchmoves: Option<Vec<ChessMove>>
+}
 
-        let mut finished_inner_search = false;
+pub struct Searcher{
+    //everything a search needs, owned by whichever thread is running it
+    pub nodes_visited: u64,//by this thread
+    pub shared_nodes: Arc<AtomicU64>,//by every thread on this search, added to in batches
+    nodes_flushed: u64,//how much of nodes_visited is already in shared_nodes
+    pub thread_id: usize,//0 is the main thread, the one that reports and picks the move
+    pub hasher: ZobristHasher,
+    pub tt: Arc<TranspositionTable>,
+    pub stop: Arc<AtomicBool>,
+    pub limits: SearchLimits,
+    pub ply: usize,//distance from the root of the node currently being searched
+    pub seldepth: usize,
+    pub pv: Vec<ChessMove>,//from the last finished iteration
+    pub multi_pv: usize,//how many root moves to find a score and pv for, 1 is a normal search
+    pub after_null_move: bool,//set just before searching the reply to a null move, so we never pass twice in a row
+    pub excluded_move: Option<ChessMove>,//set just before a singular extension search of the same node
+    pub root_depth: usize,//depth of the current iteration
+    pub position_stack: Vec<u64>,//keys of the game so far and then every position on the path to the current node
+    pub game_history_len: usize,//how much of position_stack is from the real game
+    pub halfmove_clock: u32,//plies since the last capture or pawn move, at the current node
+    pub stats: SearchStats,
+    pub pruning: PruningParams,//margins from the engine options
+    pub ordering: OrderingTables//killers and history, handed back to the engine after the search so they carry over
+}
 
-        for chess_move in moves{
-            match timer{
-                Some(t) => {
-                    let elapsed = t.elapsed().as_millis() as u32;
-                    if elapsed >= time_limit{
-                        return (max_eval,best_move,false);
-                    }
-                }
-                None => {}
-            }
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(chess_move, &mut new_board);
+impl Searcher{
+    pub fn new(hasher: ZobristHasher, tt: Arc<TranspositionTable>, stop: Arc<AtomicBool>) -> Self{
+        Searcher { 
+            nodes_visited: 0,
+            shared_nodes: Arc::new(AtomicU64::new(0)),
+            nodes_flushed: 0,
+            thread_id: 0,
+            hasher: hasher,
+            tt: tt,
+            stop: stop,
+            limits: SearchLimits::default(),
+            ply: 0,
+            seldepth: 0,
+            pv: vec![],
+            multi_pv: 1,
+            after_null_move: false,
+            excluded_move: None,
+            root_depth: 0,
+            position_stack: vec![],
+            game_history_len: 0,
+            halfmove_clock: 0,
+            stats: SearchStats::default(),
+            pruning: PruningParams::default(),
+            ordering: OrderingTables::default()
+        }
+    }
 
-            let (eval,_,finished_this_inner_search) = search_alpha_beta(engine, new_board, depth-1, alpha, beta, my_color, false, None, timer, time_limit);
-            finished_inner_search = finished_this_inner_search;
+    pub fn set_game_history(&mut self, game_hashes: Vec<u64>, halfmove_clock: u32){
+        //game_hashes are the keys of every position before the one we are searching from
+        self.game_history_len = game_hashes.len();
+        self.position_stack = game_hashes;
+        self.halfmove_clock = halfmove_clock;
+    }
 
-            if eval > max_eval{
-                max_eval = eval;
-                best_move = Some(chess_move);
-            }
+    fn push_position(&mut self, hash: u64, irreversible: bool) -> u32{
+        //call before searching a child, hash is the position we are leaving
+        //returns the old clock to hand back to pop_position
+        self.position_stack.push(hash);
+        let old_clock = self.halfmove_clock;
+        self.halfmove_clock = if irreversible {0} else {old_clock + 1};
+        self.ply += 1;
+        return old_clock;
+    }
 
-            alpha = alpha.max(eval);
-            if beta <= alpha{
-                break;
-            }
-        }
-        return (max_eval,best_move,finished_inner_search);
+    fn pop_position(&mut self, old_clock: u32){
+        self.position_stack.pop();
+        self.halfmove_clock = old_clock;
+        self.ply -= 1;
+    }
 
-    }else{
-        let mut min_eval: f32 = MATE_VALUE;
-        let mut worst_move: Option<ChessMove> = None;
-
-        let mut finished_inner_search = false;
-        for chess_move in moves{
-            match timer{
-                Some(t) => {
-                    let elapsed = t.elapsed().as_millis() as u32;
-                    //info!("----sub-elapsed: {elapsed}");
-
-                    if elapsed >= time_limit{
-                        return (min_eval,worst_move,false);
-                    }
+    fn is_repetition(&self, hash: u64) -> bool{
+        //a position seen before on the search path is a draw, we can always just repeat again
+        //from the game before the root it has to be the third time, like the real rule
+        //only positions since the last capture or pawn move with the same side to move can match
+        let n = self.position_stack.len();
+        let lookback = (self.halfmove_clock as usize).min(n);
+        let mut game_repeats = 0;
+        let mut i = 4;
+        while i <= lookback{
+            let idx = n - i;
+            if self.position_stack[idx] == hash{
+                if idx >= self.game_history_len{
+                    return true;
                 }
-                None => {
-                    //info!("couldn't match timer");
+                game_repeats += 1;
+                if game_repeats >= 2{
+                    return true;
                 }
             }
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(chess_move, &mut new_board);
+            i += 2;
+        }
+        return false;
+    }
+
+    pub fn is_main_thread(&self) -> bool{
+        return self.thread_id == 0;
+    }
 
-            let (eval,_,finished_this_inner_search) = search_alpha_beta(engine, new_board, depth-1, alpha, beta, my_color, true, None, timer, time_limit);
-            finished_inner_search = finished_this_inner_search;
+    pub fn flush_nodes(&mut self){
+        //hitting the shared counter on every node would have the threads fighting over it
+        self.shared_nodes.fetch_add(self.nodes_visited - self.nodes_flushed, Ordering::Relaxed);
+        self.nodes_flushed = self.nodes_visited;
+    }
+
+    pub fn total_nodes(&self) -> u64{
+        //nodes from every thread, close enough while a search is running
+        return self.shared_nodes.load(Ordering::Relaxed) + self.nodes_visited - self.nodes_flushed;
+    }
 
-            if eval < min_eval{
-                min_eval = eval;
-                worst_move = Some(chess_move);
+    pub fn should_stop(&mut self, time_manager: Option<&TimeManager>) -> bool{
+        //the uci thread sets the stop flag, the time manager has our own limit
+        if self.stop.load(Ordering::Relaxed){
+            return true;
+        }
+        if self.nodes_visited - self.nodes_flushed >= NODE_FLUSH_INTERVAL{
+            self.flush_nodes();
+        }
+        match self.limits.nodes{
+            Some(max_nodes) if self.total_nodes() >= max_nodes => {
+                return true;
             }
-            
-            beta = beta.min(eval);
-            if beta <= alpha{
-                break;
+            _ => {}
+        }
+        match time_manager{
+            Some(tm) => {
+                return tm.hard_limit_reached();
+            }
+            None => {
+                return false;
             }
         }
-        return (min_eval,worst_move,finished_inner_search);
-
     }
 }
 
-pub fn quiescence_search(engine: &mut ChessEngine, board: chess::Board, qs_depth_hard_limit: u32, mut alpha: f32, mut beta: f32,
-                        my_color: chess::Color, my_move: bool,
+#[allow(clippy::too_many_arguments)]//node state, window, root move list, timing and pv all travel with every call
+pub fn search_alpha_beta(searcher: &mut Searcher, board: chess::Board, state_hash: u64, mut depth: usize, mut alpha: Score, mut beta: Score, 
+                        movelist: Option<Vec<ChessMove>>,
+                        time_manager: Option<&TimeManager>, pv: &mut Vec<ChessMove>
                         )
-                        -> (f32, Option<ChessMove>, bool){
-
-    if qs_depth_hard_limit <= 0{
-        engine.nodes_visited += 1;
-        let eval = evaluate(&board, my_color);
-        return (eval,None,true);
-    }
-    
-    let stand_pat = evaluate(&board, my_color);
-    engine.nodes_visited += 1;
-    
-    if my_move{//maximizing
-        if stand_pat >= beta{
-            return (stand_pat,None,true);
+                         -> (Score, Option<ChessMove>,bool){
+    //negamax, every score is from the point of view of whoever is to move on board
+    //can take a pre-supplied move list
+    //state_hash is the zobrist key of board, kept up to date move by move instead of rehashing every node
+    //pv gets filled with the principal variation starting at this node
+
+    pv.clear();
+    searcher.seldepth = searcher.seldepth.max(searcher.ply);
+    let after_null_move = searcher.after_null_move;
+    searcher.after_null_move = false;
+    let excluded_move = searcher.excluded_move.take();
+
+    let in_check = board.checkers().popcnt() > 0;
+
+    //draws and already known shorter mates end the node before anything else
+    if searcher.ply > 0{
+        if searcher.is_repetition(state_hash){
+            return (DRAW_SCORE,None,true);
+        }
+        //fifty move rule, unless the move that got us here was mate
+        if searcher.halfmove_clock >= 100 && !(in_check && MoveGen::new_legal(&board).len() == 0){
+            return (DRAW_SCORE,None,true);
         }
-        alpha = alpha.max(stand_pat);
 
-        let all_moves = chessutil::output_sorted_move_list(&board);
-        let (captures, quiet_moves): (Vec<ChessMove>, Vec<ChessMove>) = all_moves.iter()
-            .partition(|m| board.piece_on(m.get_dest()).is_some());
-
-        for cap in captures{
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(cap, &mut new_board);
-            let (eval,_,_) = quiescence_search(engine, new_board, qs_depth_hard_limit-1,alpha, beta, my_color, false);
-            if eval >= beta{
-                return (beta,Some(cap),true);
-            }
-            alpha = alpha.max(eval);
+        //mate distance pruning: we can't do better than mating next move or worse than being mated right here,
+        //so if a shorter mate is already known somewhere above, nothing in this subtree matters
+        alpha = alpha.max(-MATE_VALUE + searcher.ply as Score);
+        beta = beta.min(MATE_VALUE - searcher.ply as Score - 1);
+        if alpha >= beta{
+            return (alpha,None,true);
         }
     }
-    else{//minimizing
-        if stand_pat <= alpha{
-            return (stand_pat,None,true);
-        }
-        beta = beta.min(stand_pat);
-        let all_moves = chessutil::output_sorted_move_list(&board);
-        let (captures, quiet_moves): (Vec<ChessMove>, Vec<ChessMove>) = all_moves.iter()
-            .partition(|m| board.piece_on(m.get_dest()).is_some());
-
-        for cap in captures{
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(cap, &mut new_board);
-            let (eval,_,_) = quiescence_search(engine, new_board, qs_depth_hard_limit-1,alpha, beta, my_color, true);
-            if eval <= alpha{
-                return (alpha,Some(cap),true);
+
+    //check extension: don't stop to evaluate in the middle of a checking sequence
+    //capped by distance from the root so a long run of checks can't blow up the tree
+    if in_check && searcher.ply < 2 * searcher.root_depth{
+        depth += 1;
+        searcher.stats.check_extensions += 1;
+    }
+
+    if depth == 0{
+        return quiescence_search(searcher, board, alpha, beta, time_manager);//counts its own node
+    }
+
+    searcher.nodes_visited += 1;
+    if searcher.should_stop(time_manager){
+        return (alpha,None,false);
+    }
+
+    let alpha_orig = alpha;
+
+    let mut tt_move: Option<ChessMove> = None;
+    let tt_entry = searcher.tt.probe(state_hash).map(|mut entry| {
+        entry.score = score_from_tt(entry.score, searcher.ply);
+        entry
+    });
+    match tt_entry{
+        Some(entry) => {
+            tt_move = entry.best_move;
+            //never cut at the root, we need a real move and pv from there
+            //and a search with a move left out isn't searching the position the entry is about
+            if searcher.ply > 0 && excluded_move.is_none() && entry.depth as usize >= depth{
+                let usable = match entry.bound{
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score >= beta,
+                    Bound::Upper => entry.score <= alpha
+                };
+                if usable{
+                    match entry.best_move{
+                        Some(cm) => pv.push(cm),
+                        None => {}
+                    }
+                    return (entry.score, entry.best_move, true);
+                }
             }
-            beta = beta.min(eval);
         }
+        None => {}
     }
-    return (stand_pat,None,true);//fallback case
-}
 
-pub fn search_alpha_beta_with_quiescence(engine: &mut ChessEngine, board: chess::Board, depth: usize, mut alpha: f32, mut beta: f32, 
-                        my_color: chess::Color, my_move: bool, movelist: Option<Vec<ChessMove>>,
-                        timer: Option<&std::time::Instant>, time_limit: u32
-                        )
-                         -> (f32, Option<ChessMove>,bool){
-    /*
-    This function is pretty bad. There is probably a bug, but also in most moves we are not hitting the ply limit
-    We time out on most moves, so therefore going deeper on a few of them doesn't really do much I think
-     */
+    let stm = board.side_to_move();
+    let static_eval = if in_check {-INFINITE_SCORE} else {evaluate(&board, stm)};//nothing static about a position in check
+    let pv_node = beta - alpha > 1;
+    let can_prune = searcher.ply > 0 && !in_check && !pv_node;
 
-    const MATE_VALUE: f32 = 100000.0;
+    //reverse futility: we are so far above beta that even a bad move at this depth won't drop us below it
+    if can_prune && depth <= REVERSE_FUTILITY_MAX_DEPTH && beta.abs() < MATE_BOUND
+        && static_eval - searcher.pruning.reverse_futility_margin * depth as Score >= beta{
+        searcher.stats.reverse_futility_cutoffs += 1;
+        return (static_eval,None,true);
+    }
 
+    //razoring: so far below alpha that only a capture could save it, let quiescence decide
+    if can_prune && depth <= RAZOR_MAX_DEPTH && alpha.abs() < MATE_BOUND
+        && static_eval + searcher.pruning.razor_margin * depth as Score <= alpha{
+        let (q_score,_,finished) = quiescence_search(searcher, board, alpha, beta, time_manager);
+        if !finished{
+            return (alpha,None,false);
+        }
+        if q_score <= alpha{
+            searcher.stats.razor_cutoffs += 1;
+            return (q_score,None,true);
+        }
+    }
 
-    if depth == 0{
-        let (eval,_,_) =  quiescence_search(engine, board, 1,alpha, beta, my_color, my_move);
-        return (eval,None,true);
+    //null move pruning: let the opponent move twice, if we are still above beta with a shallower search
+    //then a real move would almost certainly be too, so don't bother finding it
+    //passing can be the best move in pawn endgames (zugzwang) so those are skipped, and deep cutoffs get checked
+    let has_pieces = (board.color_combined(stm) & !(board.pieces(Piece::Pawn) | board.pieces(Piece::King))).popcnt() > 0;
+    if searcher.ply > 0 && !after_null_move && excluded_move.is_none() && !in_check && has_pieces && depth >= NULL_MOVE_MIN_DEPTH
+        && beta.abs() < MATE_BOUND && static_eval >= beta{
+        match board.null_move(){
+            Some(null_board) => {
+                let reduction = 3 + depth / 6;
+                let null_depth = depth.saturating_sub(1 + reduction);
+                let null_hash = searcher.hasher.hash_after_null_move(&board, state_hash, &null_board);
+                let mut null_pv: Vec<ChessMove> = vec![];
+
+                let clock = searcher.push_position(state_hash, true);//nothing repeats across a pass
+                searcher.after_null_move = true;
+                let (child_score,_,finished) = search_alpha_beta(searcher, null_board, null_hash, null_depth, -beta, -beta+1, None, time_manager, &mut null_pv);
+                searcher.after_null_move = false;
+                searcher.pop_position(clock);
+                if !finished{
+                    return (beta,None,false);
+                }
+
+                let mut null_score = -child_score;
+                if null_score >= beta{
+                    if null_score >= MATE_BOUND{
+                        null_score = beta;//a mate found by passing isn't a real mate
+                    }
+                    if depth < NULL_MOVE_VERIFY_DEPTH{
+                        searcher.stats.null_move_cutoffs += 1;
+                        return (null_score,None,true);
+                    }
+                    //deep enough that a wrong cutoff is expensive, search this node for real (without passing) at the reduced depth
+                    let mut verify_pv: Vec<ChessMove> = vec![];
+                    searcher.after_null_move = true;
+                    let (verify_score,_,finished) = search_alpha_beta(searcher, board, state_hash, null_depth, beta-1, beta, None, time_manager, &mut verify_pv);
+                    searcher.after_null_move = false;
+                    if !finished{
+                        return (beta,None,false);
+                    }
+                    if verify_score >= beta{
+                        searcher.stats.null_move_cutoffs += 1;
+                        return (null_score,None,true);
+                    }
+                }
+            }
+            None => {}
+        }
     }
 
     let mut moves: Vec<ChessMove> = vec![];
@@ -220,339 +412,489 @@ pub fn search_alpha_beta_with_quiescence(engine: &mut ChessEngine, board: chess:
             moves = ml;
         }
         None => {
-            moves = chessutil::output_sorted_move_list(&board);
+            moves = chessutil::output_sorted_move_list_with_history(&board, &searcher.ordering, searcher.ply);
+            match tt_move{//best move from the last time we saw this position goes first
+                Some(cm) => {
+                    if let Some(idx) = moves.iter().position(|&m| m == cm){
+                        moves.remove(idx);
+                        moves.insert(0, cm);
+                    }
+                }
+                None => {}
+            }
+            match excluded_move{
+                Some(em) => moves.retain(|&m| m != em),
+                None => {}
+            }
         }
     }
-    
-    let num_moves = moves.len();
-    //info!("{num_moves} moves!!!!!!");
 
     if moves.is_empty(){
-        match board.status(){
-            BoardStatus::Stalemate => {
-                return (0.0,None,true);
+        if excluded_move.is_some(){
+            //the left out move was the only one, so it is as singular as it gets
+            return (alpha,None,true);
+        }
+        if in_check{
+            //checkmate, the further from the root the less bad it is
+            return (-MATE_VALUE + searcher.ply as Score,None,true);
+        }
+        return (DRAW_SCORE,None,true);//stalemate
+    }
+
+    let mut best_score: Score = -INFINITE_SCORE;
+    let mut best_move: Option<ChessMove> = None;
+    let mut finished_inner_search = false;
+    let mut tried_quiets: Vec<ChessMove> = vec![];
+
+    //singular extension: if the table says one move is best here and every other move is clearly worse
+    //(checked by a shallow search with that move left out), it is the only move and deserves another ply
+    let mut extend_tt_move = false;
+    match (tt_entry, tt_move){
+        (Some(entry), Some(cm)) if searcher.ply > 0 && searcher.ply < 2 * searcher.root_depth && excluded_move.is_none() && depth >= SINGULAR_MIN_DEPTH
+                && entry.depth as usize + 3 >= depth && entry.bound != Bound::Upper && entry.score.abs() < MATE_BOUND => {
+            let singular_beta = entry.score - 2 * depth as Score;
+            let mut singular_pv: Vec<ChessMove> = vec![];
+            searcher.excluded_move = Some(cm);
+            let (singular_score,_,finished) = search_alpha_beta(searcher, board, state_hash, (depth - 1) / 2, singular_beta - 1, singular_beta, None, time_manager, &mut singular_pv);
+            searcher.excluded_move = None;
+            if !finished{
+                return (alpha,None,false);
             }
-            BoardStatus::Checkmate => {
-                if board.side_to_move() == my_color{
-                    let eval = -MATE_VALUE - (depth as f32);
-                    return (eval,None,true)
-                }else{
-                    let eval = MATE_VALUE + (depth as f32);
-                    return (eval,None,true)
-                }
+            if singular_score < singular_beta{
+                extend_tt_move = true;
+                searcher.stats.singular_extensions += 1;
             }
-            BoardStatus::Ongoing => {}
-            _ => {}
         }
+        _ => {}
     }
 
-    
-    if my_move{
-        let mut max_eval: f32 = -MATE_VALUE;
-        let mut best_move: Option<ChessMove> = None;
+    //futility: near the leaves, if a quiet move would need to gain more than the margin to reach alpha, skip it
+    let futile = searcher.ply > 0 && !in_check && depth <= FUTILITY_MAX_DEPTH && alpha.abs() < MATE_BOUND
+        && static_eval + searcher.pruning.futility_margin * depth as Score <= alpha;
 
-        let mut finished_inner_search = false;
-
-        for chess_move in moves{
-            match timer{
-                Some(t) => {
-                    let elapsed = t.elapsed().as_millis() as u32;
-                    if elapsed >= time_limit{
-                        return (max_eval,best_move,false);
-                    }
-                }
-                None => {}
-            }
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(chess_move, &mut new_board);
-
-            let move_is_capture = board.piece_on(chess_move.get_dest()).is_some();
+    for (move_index, chess_move) in moves.into_iter().enumerate(){
+        if searcher.should_stop(time_manager){
+            return (best_score,best_move,false);
+        }
+        let quiet = chessutil::is_quiet(&board, chess_move);
+        let new_depth = if extend_tt_move && Some(chess_move) == tt_move {depth} else {depth - 1};
+        let new_board = board.make_move_new(chess_move);
+        let gives_check = new_board.checkers().popcnt() > 0;
+        let boring = quiet && !in_check && !gives_check;
+
+        if futile && boring && move_index > 0 && best_score > -MATE_BOUND{
+            searcher.stats.futility_pruned += 1;
+            continue;
+        }
 
-            
-            let (eval,_,finished_this_inner_search) = search_alpha_beta_with_quiescence(engine, new_board, depth-1, alpha, beta, my_color, false, None, timer, time_limit);
-            
-            finished_inner_search = finished_this_inner_search;
+        //late move pruning: near the leaves, quiet moves this far down the list basically never matter
+        if searcher.ply > 0 && boring && depth <= LMP_MAX_DEPTH && best_score > -MATE_BOUND && move_index >= 3 + depth * depth{
+            searcher.stats.lmp_pruned += 1;
+            continue;
+        }
 
-            if eval > max_eval{
-                max_eval = eval;
-                best_move = Some(chess_move);
+        //late move reductions: the ordering says this move is probably bad, so look at it less deeply first
+        let mut reduction = 0;
+        if move_index >= LMR_MIN_MOVES && depth >= LMR_MIN_DEPTH && boring{
+            reduction = lmr_reduction(depth, move_index);
+            if searcher.ordering.is_killer(searcher.ply, chess_move).is_some(){
+                reduction = reduction.saturating_sub(1);
             }
+            reduction = reduction.min(depth - 2);//always leave at least one ply
+        }
 
-            alpha = alpha.max(eval);
-            if beta <= alpha{
-                break;
+        let new_hash = searcher.hasher.hash_after_move(&board, state_hash, chess_move, &new_board);
+
+        let mut child_pv: Vec<ChessMove> = vec![];
+        let irreversible = !quiet || board.piece_on(chess_move.get_source()) == Some(Piece::Pawn);
+        let clock = searcher.push_position(state_hash, irreversible);
+        //pvs: the first move is expected to be best, so the rest only have to prove they aren't better
+        //that takes a null window, and only a move that beats alpha gets searched again properly
+        let mut child = if move_index == 0{
+            search_alpha_beta(searcher, new_board, new_hash, new_depth, -beta, -alpha, None, time_manager, &mut child_pv)
+        }else{
+            search_alpha_beta(searcher, new_board, new_hash, new_depth - reduction, -alpha-1, -alpha, None, time_manager, &mut child_pv)
+        };
+        if reduction > 0{
+            searcher.stats.lmr_searches += 1;
+            if child.2 && -child.0 > alpha{
+                //the reduced search thinks it's good after all, check at full depth
+                searcher.stats.lmr_researches += 1;
+                child = search_alpha_beta(searcher, new_board, new_hash, new_depth, -alpha-1, -alpha, None, time_manager, &mut child_pv);
             }
         }
-        return (max_eval,best_move,finished_inner_search);
+        if move_index > 0 && child.2 && -child.0 > alpha && -child.0 < beta{
+            searcher.stats.pvs_researches += 1;
+            child = search_alpha_beta(searcher, new_board, new_hash, new_depth, -beta, -alpha, None, time_manager, &mut child_pv);
+        }
+        searcher.pop_position(clock);
+        let (child_score,_,finished_this_inner_search) = child;
+        let score = -child_score;
+        finished_inner_search = finished_this_inner_search;
+
+        if score > best_score{
+            best_score = score;
+            best_move = Some(chess_move);
+            pv.clear();
+            pv.push(chess_move);
+            pv.extend(child_pv);
+        }
 
-    }else{
-        let mut min_eval: f32 = MATE_VALUE;
-        let mut worst_move: Option<ChessMove> = None;
-
-        let mut finished_inner_search = false;
-        for chess_move in moves{
-            match timer{
-                Some(t) => {
-                    let elapsed = t.elapsed().as_millis() as u32;
-                    //info!("----sub-elapsed: {elapsed}");
-
-                    if elapsed >= time_limit{
-                        return (min_eval,worst_move,false);
-                    }
-                }
-                None => {
-                    //info!("couldn't match timer");
-                }
-            }
-            let board_copy = board.clone();
-            let mut new_board = board.clone();
-            board_copy.make_move(chess_move, &mut new_board);
-
-            //if chess_move is a capture, search to depth d, and not d-1
-            let move_is_capture = board.piece_on(chess_move.get_dest()).is_some();
-
-                
-            let (eval,_,finished_this_inner_search) = search_alpha_beta_with_quiescence(engine, new_board, depth-1, alpha, beta, my_color, false, None, timer, time_limit);
-        
-            
-            finished_inner_search = finished_this_inner_search;
-
-            if eval < min_eval{
-                min_eval = eval;
-                worst_move = Some(chess_move);
-            }
-            
-            beta = beta.min(eval);
-            if beta <= alpha{
-                break;
+        alpha = alpha.max(score);
+        if alpha >= beta{
+            if quiet && finished_inner_search{
+                searcher.ordering.update_on_cutoff(board.side_to_move(), searcher.ply, depth, chess_move, &tried_quiets);
             }
+            break;
+        }
+        if quiet{
+            tried_quiets.push(chess_move);
         }
-        return (min_eval,worst_move,finished_inner_search);
+    }
 
+    if finished_inner_search && excluded_move.is_none(){
+        store_in_tt(searcher, state_hash, depth, best_score, best_move, alpha_orig, beta);
     }
+    return (best_score,best_move,finished_inner_search);
 }
 
+fn aspiration_search(searcher: &mut Searcher, board: chess::Board, depth: usize, prev_score: Option<Score>,
+                        moves: Vec<ChessMove>, time_manager: &TimeManager, pv: &mut Vec<ChessMove>)
+                        -> (Score, Option<ChessMove>, bool){
+    //searches a small window around last iteration's score, the score rarely moves much between depths
+    //if it lands outside, widen that side and try again until it fits
+    let mut alpha = -INFINITE_SCORE;
+    let mut beta = INFINITE_SCORE;
+    let mut delta = ASPIRATION_WINDOW;
+    let root_hash = searcher.hasher.hash_board(&board);
+    searcher.root_depth = depth;
+    match prev_score{
+        Some(s) if depth >= ASPIRATION_MIN_DEPTH && s.abs() < MATE_BOUND => {
+            alpha = (s - delta).max(-INFINITE_SCORE);
+            beta = (s + delta).min(INFINITE_SCORE);
+        }
+        _ => {}
+    }
 
-pub fn iterative_deepening_search_with_time(engine: &mut ChessEngine, board: chess::Board, max_depth: usize, base_time_scale: f32, hard_time_limit: u32, my_color: chess::Color, my_move: bool)
-                                    -> (f32, Option<ChessMove>){
+    loop{
+        let (score, bm, finished) = search_alpha_beta(searcher, board, root_hash, depth, alpha, beta, Some(moves.clone()), Some(time_manager), pv);
+        if !finished{
+            return (score, bm, false);
+        }
+        if score <= alpha && alpha > -INFINITE_SCORE{
+            //failed low, the move it found can't be trusted yet
+            alpha = (score - delta).max(-INFINITE_SCORE);
+        }else if score >= beta && beta < INFINITE_SCORE{
+            beta = (score + delta).min(INFINITE_SCORE);
+        }else{
+            return (score, bm, true);
+        }
+        delta *= 2;
+        if delta > ASPIRATION_GIVE_UP{
+            alpha = -INFINITE_SCORE;
+            beta = INFINITE_SCORE;
+        }
+        info!("Aspiration re-search at depth {depth}: {alpha}..{beta}");
+    }
+}
 
+fn store_in_tt(searcher: &Searcher, hash: u64, depth: usize, score: Score, best_move: Option<ChessMove>,
+                alpha_orig: Score, beta: Score){
+    //works out the bound from the window the node was searched with
+    let bound = if score <= alpha_orig{
+        Bound::Upper
+    }else if score >= beta{
+        Bound::Lower
+    }else{
+        Bound::Exact
+    };
+    searcher.tt.store(hash, depth.min(u8::MAX as usize) as u8, score_to_tt(score, searcher.ply), bound, best_move);
+}
 
-    let base_time = ((hard_time_limit as f32) / base_time_scale) as u32;
+fn score_to_tt(score: Score, ply: usize) -> Score{
+    //mate scores count plies from the root, but the same position can be reached at any ply
+    //so the table keeps them as distance from this node instead
+    if score >= MATE_BOUND{
+        return score + ply as Score;
+    }
+    if score <= -MATE_BOUND{
+        return score - ply as Score;
+    }
+    return score;
+}
 
-    const MATE_VALUE: f32 = 100000.0;
-    
-    let timer = std::time::Instant::now();
+fn score_from_tt(score: Score, ply: usize) -> Score{
+    if score >= MATE_BOUND{
+        return score - ply as Score;
+    }
+    if score <= -MATE_BOUND{
+        return score + ply as Score;
+    }
+    return score;
+}
 
-    let mut target_duration = base_time;
-    let mut difficulty_mult: f32 = 1.0;  
+pub fn quiescence_search(searcher: &mut Searcher, board: chess::Board, mut alpha: Score, beta: Score,
+                        time_manager: Option<&TimeManager>)
+                        -> (Score, Option<ChessMove>, bool){
+    //only looks at captures and queen promotions so we don't stop the search in the middle of a trade
+    //when in check every evasion is searched and there is no standing pat
 
+    searcher.nodes_visited += 1;
+    searcher.seldepth = searcher.seldepth.max(searcher.ply);
 
-    let mut best_move: Option<ChessMove> = None;
-    let mut prev_best_move: Option<ChessMove> = None;
+    if searcher.should_stop(time_manager){
+        return (alpha,None,false);
+    }
 
-    let mut best_score: f32 = -MATE_VALUE;
+    let in_check = board.checkers().popcnt() > 0;
+    if searcher.ply >= MAX_PLY{
+        return (evaluate(&board, board.side_to_move()),None,true);
+    }
 
-    let mut score_history: Vec<i32>  = vec![];
+    let mut best_score: Score = -INFINITE_SCORE;
+    let mut moves: Vec<ChessMove> = vec![];
+    let mut stand_pat: Score = -INFINITE_SCORE;
 
-    'depth_loop: for depth in 1..max_depth{
-        let mut elapsed_time = timer.elapsed().as_millis() as u32;
-        info!("Depth: {depth}   Elapsed: {elapsed_time}");
-        if elapsed_time >= target_duration{
-            break 'depth_loop;
+    if in_check{
+        moves = chessutil::output_sorted_move_list(&board);
+        if moves.is_empty(){
+            return (-MATE_VALUE + searcher.ply as Score,None,true);
         }
-        if elapsed_time >= hard_time_limit{
-            break 'depth_loop;
+    }else{
+        stand_pat = evaluate(&board, board.side_to_move());
+        if stand_pat >= beta{
+            return (stand_pat,None,true);
+        }
+        //even winning a queen for free wouldn't get us back to alpha, don't bother
+        if stand_pat + piece_value(Piece::Queen) + QS_DELTA_MARGIN < alpha{
+            return (stand_pat,None,true);
         }
+        alpha = alpha.max(stand_pat);
+        best_score = stand_pat;
+        moves = chessutil::output_capture_list(&board);
+    }
 
-        let mut moves = chessutil::output_sorted_move_list(&board);
-        match prev_best_move{//prepend the previous best move to list if it exists
-                //there exist other schemes such as using the list of moves sorted by evaluation
-                //but in general just using the best move first is ok
-                //storing the entire list and sorting it provides minimal gain for a lot (?) of computation
-                Some(cm) => {
-                    moves.retain(|&m| m!= cm);
-                    moves.insert(0, cm);
+    let mut best_move: Option<ChessMove> = None;
 
-                }
-                None =>{}
-            }
-        
-        let num_moves = moves.len();
-
-        let mut curr_best_move: Option<ChessMove> = None;
-        let mut curr_best_score: f32 = -MATE_VALUE;
-
-        let mut finished_this_depth = true;
-        
-
-        let ab_time_limit = target_duration.min(hard_time_limit);//in case the hard limit is sooner than our new limit, send that one
-        let (eval,bm,finished_ab) = search_alpha_beta(engine, board, depth, -MATE_VALUE, MATE_VALUE, my_color, my_move, Some(moves), Some(&timer),ab_time_limit);
-                
-        
-        if eval > curr_best_score{
-            curr_best_score = eval;
-            curr_best_move = bm;
-        }
-
-    
-        if finished_ab{
-            match curr_best_move{
-                Some(cbm) =>{
-                    best_move = curr_best_move;
-                    best_score = curr_best_score;
-                    prev_best_move = curr_best_move;
-                }
-                None => {}
+    for chess_move in moves{
+        //delta pruning: skip captures that can't raise alpha even with a positional bonus on top
+        if !in_check && chess_move.get_promotion().is_none(){
+            let victim = match board.piece_on(chess_move.get_dest()){
+                Some(p) => piece_value(p),
+                None => piece_value(Piece::Pawn)//en passant
+            };
+            if stand_pat + victim + QS_DELTA_MARGIN < alpha{
+                continue;
             }
-        }
-
-        //---------adjust timer for unstable evaluations-------------
-        //best move instability
-        if prev_best_move.is_some() && curr_best_move != prev_best_move{
-            difficulty_mult *= 1.30;
-        }
-
-        //score history instability
-        score_history.push((best_score*1000.0) as i32);//since floats don't have a max()
-        if score_history.len() >= 3{
-            let default_eval: i32 = 0;
-            let max_recent_score = score_history[score_history.len()-3..].iter().max().unwrap_or(&default_eval);
-            let min_recent_score = score_history[score_history.len()-3..].iter().min().unwrap_or(&default_eval);
-            if ((max_recent_score - min_recent_score) as f32)/1000.0 > 10.0{//this 15 number is arbitrary
-                difficulty_mult *= 1.25;
+            //captures that lose material once the square is traded off can't help here either
+            if !see_ge(&board, chess_move, 0){
+                continue;
             }
-            
         }
 
-        //close alternatives check, not currently possible
-
-        //tactical indicator check
-        //if we are currently in check
-        let num_checkers = board.checkers().popcnt();
-        if num_checkers > 0{
-            difficulty_mult *= 1.2;
+        let new_board = board.make_move_new(chess_move);
+        searcher.ply += 1;
+        let (child_score,_,finished) = quiescence_search(searcher, new_board, -beta, -alpha, time_manager);
+        searcher.ply -= 1;
+        if !finished{
+            return (best_score.max(alpha),best_move,false);
         }
+        let score = -child_score;
 
-        //if our best move puts the opponent in check
-        match best_move{
-            Some(bm) => {
-                let board_after_bm = board.make_move_new(bm);
-                let num_checkers = board_after_bm.checkers().popcnt();
-                if num_checkers > 0{
-                    difficulty_mult *= 1.2;
-                }
-            }
-            None => {}
+        if score > best_score{
+            best_score = score;
+            best_move = Some(chess_move);
         }
-        
-
-
-        match best_move{
-            Some(bm) => {
-                //if our best move is a capture
-                let bm_is_a_capture = board.piece_on(bm.get_dest()).is_some();
-                if bm_is_a_capture{
-                    difficulty_mult *= 1.2;
-                }
-
-                match board.piece_on(bm.get_source()){
-                    Some(p) => {
-                        //if our best move is a promotion
-                        if p == chess::Piece::Pawn && (bm.get_dest().get_rank()  == chess::Rank::Eighth || bm.get_dest().get_rank()  == chess::Rank::First){
-                            difficulty_mult *= 1.2;//promotion
-                        }
-                    }
-                    None => {}
-                }
-                
-            }
-            None => {}
+        alpha = alpha.max(score);
+        if alpha >= beta{
+            break;
         }
-
-        target_duration = ((base_time as f32) * difficulty_mult.min(base_time_scale)) as u32;
-    
-        elapsed_time = timer.elapsed().as_millis() as u32;
-
-        
-        info!("End of D{depth}     Elapsed: {elapsed_time}");
-        info!("Difficulty mult for next iteration: {difficulty_mult}");
-        info!("New time limit: {target_duration}");
-
-
     }
+    return (best_score,best_move,true);
+}
 
-    return (best_score,best_move);
 
+struct RootLine{
+    //one multipv candidate from a finished iteration
+    score: Score,
+    chessmove: ChessMove,
+    pv: Vec<ChessMove>
 }
 
-pub fn iterative_deepening_search(engine: &mut ChessEngine, board: chess::Board, max_depth: usize, time_limit: u32, my_color: chess::Color, my_move: bool)
-                                    -> (f32, Option<ChessMove>){
-    const MATE_VALUE: f32 = 100000.0;
-    
-    let timer = std::time::Instant::now();
+pub fn iterative_deepening_search_with_time(searcher: &mut Searcher, board: chess::Board, max_depth: usize, time_manager: &mut TimeManager)
+                                    -> (Score, Option<ChessMove>){
+    //the time manager says when to stop starting new iterations (soft) and when to abort one (hard)
 
     let mut best_move: Option<ChessMove> = None;
     let mut prev_best_move: Option<ChessMove> = None;
 
-    let mut best_score: f32 = -MATE_VALUE;
+    let mut best_score: Score = -INFINITE_SCORE;
+    let mut lines: Vec<RootLine> = vec![];//best first
+
+    let mut score_history: Vec<Score>  = vec![];
+    searcher.seldepth = 0;
+    searcher.ordering.new_search();
 
+    //helper threads start on odd or even depths so they aren't all doing exactly what the main thread is,
+    //the point is to fill the shared table with things the main thread will want soon
+    let first_depth = 1 + searcher.thread_id % 2;
 
-    'depth_loop: for depth in 1..max_depth{
-        let mut elapsed_time = timer.elapsed().as_millis() as u32;
+    'depth_loop: for depth in first_depth..max_depth{
+        let mut elapsed_time = time_manager.elapsed_ms();
         info!("Depth: {depth}   Elapsed: {elapsed_time}");
-        if elapsed_time >= time_limit{
+        if !time_manager.should_start_iteration(){
+            break 'depth_loop;
+        }
+        if searcher.should_stop(None){
             break 'depth_loop;
         }
 
-        let mut moves = chessutil::output_sorted_move_list(&board);
-        match prev_best_move{//prepend the previous best move to list if it exists
+        let mut moves = root_move_list(searcher, &board);
+        for line in lines.iter().rev(){//prepend the previous best moves to list, best first
                 //there exist other schemes such as using the list of moves sorted by evaluation
                 //but in general just using the best move first is ok
                 //storing the entire list and sorting it provides minimal gain for a lot (?) of computation
-                Some(cm) => {
-                    moves.retain(|&m| m!= cm);
-                    moves.insert(0, cm);
+                moves.retain(|&m| m != line.chessmove);
+                moves.insert(0, line.chessmove);
+            }
 
-                }
-                None =>{}
+        //multipv: find the best move, then search again without it for the second best, and so on
+        let num_lines = searcher.multi_pv.max(1).min(moves.len().max(1));
+        let mut new_lines: Vec<RootLine> = vec![];
+        for k in 0..num_lines{
+            let remaining: Vec<ChessMove> = moves.iter().copied().filter(|&m| !new_lines.iter().any(|l| l.chessmove == m)).collect();
+            let mut pv: Vec<ChessMove> = vec![];
+            let prev_score = lines.get(k).map(|l| l.score);
+            let (eval,bm,finished_ab) = aspiration_search(searcher, board, depth, prev_score, remaining, time_manager, &mut pv);
+
+            if !finished_ab{
+                //hard limit or stop, a half finished iteration can't be trusted
+                break 'depth_loop;
             }
-        
-        let num_moves = moves.len();
-
-        let mut curr_best_move: Option<ChessMove> = None;
-        let mut curr_best_score: f32 = -MATE_VALUE;
-
-        let mut finished_this_depth = true;
-        
-        //let (eval,bm,finished_ab) = search_alpha_beta_with_quiescence(engine, board, depth, -MATE_VALUE, MATE_VALUE, my_color, my_move, Some(moves), Some(&timer),time_limit);
-        let (eval,bm,finished_ab) = search_alpha_beta(engine, board, depth, -MATE_VALUE, MATE_VALUE, my_color, my_move, Some(moves), Some(&timer),time_limit);
-        
-        if eval > curr_best_score{
-            curr_best_score = eval;
-            curr_best_move = bm;
-        }
-
-    
-        if finished_ab{
-            match curr_best_move{
-                Some(cbm) =>{
-                    best_move = curr_best_move;
-                    best_score = curr_best_score;
-                    prev_best_move = curr_best_move;
-                }
-                None => {}
+            match bm{
+                Some(cbm) => new_lines.push(RootLine { score: eval, chessmove: cbm, pv: pv }),
+                None => break
+            }
+        }
+        if new_lines.is_empty(){
+            break 'depth_loop;//no legal moves, nothing to find
+        }
+
+        //the later searches can come back better than an earlier one, this iteration's order is by score
+        new_lines.sort_by_key(|l| -l.score);
+        lines = new_lines;
+
+        let bm = Some(lines[0].chessmove);
+        let best_move_changed = prev_best_move.is_some() && bm != prev_best_move;
+        best_move = bm;
+        best_score = lines[0].score;
+        prev_best_move = bm;
+        if searcher.is_main_thread(){
+            for (k, line) in lines.iter().enumerate(){
+                print_uci_info(searcher, depth, k + 1, line.score, time_manager.start(), &line.pv);
+            }
+        }
+        searcher.pv = lines[0].pv.clone();
+
+        //go mate N is done as soon as we have a mate that short
+        match (searcher.limits.mate, mate_in_moves(best_score)){
+            (Some(mate_limit), Some(mate_moves)) if mate_moves > 0 && mate_moves as usize <= mate_limit => {
+                break 'depth_loop;
             }
+            _ => {}
         }
-    
-        elapsed_time = timer.elapsed().as_millis() as u32;
+
+        //---------adjust timer for unstable evaluations-------------
+        score_history.push(best_score);
+        time_manager.adjust_for_instability(&board, best_move, best_move_changed, &score_history);
+
+        elapsed_time = time_manager.elapsed_ms();
         info!("End of D{depth}     Elapsed: {elapsed_time}");
+        info!("Search stats: {}", searcher.stats);
+        info!("Instability for next iteration: {}", time_manager.instability());
+        info!("New soft limit: {}", time_manager.soft_limit_ms());
+    }
 
+    if best_move.is_none(){
+        //stopped before depth 1 finished, any legal move beats sending nothing
+        best_move = root_move_list(searcher, &board).first().copied();
     }
 
+    searcher.flush_nodes();
     return (best_score,best_move);
 
 }
 
+pub fn root_move_list(searcher: &Searcher, board: &chess::Board) -> Vec<ChessMove>{
+    //the sorted legal moves, cut down to the searchmoves from the go command if there were any
+    let mut moves = chessutil::output_sorted_move_list(board);
+    match &searcher.limits.searchmoves{
+        Some(searchmoves) => {
+            let all_moves = moves.clone();
+            moves.retain(|m| searchmoves.contains(m));
+            if moves.is_empty(){
+                //none of them were legal, ignore the restriction instead of having nothing to play
+                moves = all_moves;
+            }
+        }
+        None => {}
+    }
+    return moves;
+}
 
+pub fn ponder_move(searcher: &Searcher, board: &chess::Board, best_move: Option<ChessMove>) -> Option<ChessMove>{
+    //the reply we expect, so the gui can let us think on the opponent's time
+    //the pv can be just one move long after a table cut, then the table might still know the reply
+    let best_move = best_move?;
+    if searcher.pv.first() == Some(&best_move) && let Some(&reply) = searcher.pv.get(1){
+        return Some(reply);
+    }
+    let new_board = board.make_move_new(best_move);
+    let new_hash = searcher.hasher.hash_board(&new_board);
+    match searcher.tt.probe(new_hash){
+        Some(entry) => {
+            match entry.best_move{
+                Some(m) if new_board.legal(m) => return Some(m),
+                _ => return None
+            }
+        }
+        None => return None
+    }
+}
+
+pub fn mate_in_moves(score: Score) -> Option<i32>{
+    //mates are MATE_VALUE minus the plies from the root to the mate
+    //positive if we are mating, negative if we are getting mated
+    if score.abs() < MATE_BOUND{
+        return None;
+    }
+    let plies_to_mate = MATE_VALUE - score.abs();
+    let moves_to_mate = (plies_to_mate + 1) / 2;
+    if score > 0{
+        return Some(moves_to_mate);
+    }
+    return Some(-moves_to_mate);
+}
+
+pub fn uci_score_string(score: Score) -> String{
+    match mate_in_moves(score){
+        Some(mate_moves) => {
+            return format!("mate {}", mate_moves);
+        }
+        None => {
+            return format!("cp {}", score);
+        }
+    }
+}
+
+pub fn print_uci_info(searcher: &Searcher, depth: usize, multipv: usize, score: Score, timer: &std::time::Instant, pv: &[ChessMove]){
+    let elapsed_ms = timer.elapsed().as_millis() as u64;
+    let nodes = searcher.total_nodes();
+    let nps = nodes * 1000 / elapsed_ms.max(1);
+    let pv_string: Vec<String> = pv.iter().map(|m| m.to_string()).collect();
+
+    println!("info depth {} seldepth {} multipv {} score {} nodes {} nps {} time {} hashfull {} pv {}",
+        depth, searcher.seldepth, multipv, uci_score_string(score), nodes, nps, elapsed_ms, searcher.tt.hashfull(), pv_string.join(" "));
+    io::stdout().flush().unwrap();
+}
 
@@ -0,0 +1,177 @@
+use chess::{BitBoard, Board, ChessMove, Color, Piece, Rank, Square};
+use crate::evaluation::{piece_value, Score};
+
+const CHEAPEST_FIRST: [Piece; 6] = [Piece::Pawn, Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen, Piece::King];
+
+pub fn attackers_to(board: &Board, square: Square, occupied: BitBoard) -> BitBoard{
+    //every piece of either color attacking square, with sliders blocked by occupied
+    //taking pieces out of occupied as they trade off is what lets the x-rays behind them through
+    let white_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::White);
+    let black_pawns = board.pieces(Piece::Pawn) & board.color_combined(Color::Black);
+    let diagonal = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let straight = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+
+    //a white pawn attacks square if a black pawn on square would attack it, and the other way round
+    let attackers = chess::get_pawn_attacks(square, Color::Black, white_pawns)
+        | chess::get_pawn_attacks(square, Color::White, black_pawns)
+        | (chess::get_knight_moves(square) & board.pieces(Piece::Knight))
+        | (chess::get_king_moves(square) & board.pieces(Piece::King))
+        | (chess::get_bishop_moves(square, occupied) & diagonal)
+        | (chess::get_rook_moves(square, occupied) & straight);
+    return attackers & occupied;
+}
+
+fn is_promotion_square(square: Square) -> bool{
+    return square.get_rank() == Rank::First || square.get_rank() == Rank::Eighth;
+}
+
+pub fn see(board: &Board, chessmove: ChessMove) -> Score{
+    //material the side to move gains from chessmove and the trades that follow on its destination
+    //cheapest attacker first and either side can stop when carrying on loses more, pins and checks are ignored
+    let from = chessmove.get_source();
+    let to = chessmove.get_dest();
+    let mover = board.piece_on(from).expect("there should always be a piece at the source of a move");
+
+    let mut occupied = board.combined() ^ BitBoard::from_square(from);
+    let mut gain: [Score; 40] = [0; 40];
+
+    gain[0] = match board.piece_on(to){
+        Some(p) => piece_value(p),
+        None => {
+            if mover == Piece::Pawn && from.get_file() != to.get_file(){
+                //en passant, the pawn we take isn't on the destination square
+                let captured = Square::make_square(from.get_rank(), to.get_file());
+                occupied ^= BitBoard::from_square(captured);
+                piece_value(Piece::Pawn)
+            }else{
+                0
+            }
+        }
+    };
+
+    //value of whatever is standing on the square and can be taken next
+    let mut on_square = piece_value(mover);
+    match chessmove.get_promotion(){
+        Some(p) => {
+            gain[0] += piece_value(p) - piece_value(Piece::Pawn);
+            on_square = piece_value(p);
+        }
+        None => {}
+    }
+
+    let mut side = !board.side_to_move();
+    let mut d = 0;
+    loop{
+        let attackers = attackers_to(board, to, occupied);
+        let ours = attackers & board.color_combined(side);
+        if ours.popcnt() == 0{
+            break;
+        }
+
+        let mut capturer = Piece::King;
+        let mut capturer_square = ours.to_square();
+        for piece in CHEAPEST_FIRST{
+            let of_type = ours & board.pieces(piece);
+            if of_type.popcnt() > 0{
+                capturer = piece;
+                capturer_square = of_type.to_square();
+                break;
+            }
+        }
+
+        //the king can only take if nothing is left to take it back
+        if capturer == Piece::King && (attackers & board.color_combined(!side)).popcnt() > 0{
+            break;
+        }
+
+        d += 1;
+        gain[d] = on_square - gain[d-1];
+        on_square = piece_value(capturer);
+        if capturer == Piece::Pawn && is_promotion_square(to){
+            gain[d] += piece_value(Piece::Queen) - piece_value(Piece::Pawn);
+            on_square = piece_value(Piece::Queen);
+        }
+
+        occupied ^= BitBoard::from_square(capturer_square);
+        side = !side;
+
+        if d == gain.len() - 1{
+            break;//can't happen with 32 pieces, but don't index off the end
+        }
+    }
+
+    //walk back up, each side only keeps capturing if it helps them
+    while d > 0{
+        gain[d-1] = -((-gain[d-1]).max(gain[d]));
+        d -= 1;
+    }
+    return gain[0];
+}
+
+pub fn see_ge(board: &Board, chessmove: ChessMove, threshold: Score) -> bool{
+    //true if chessmove wins at least threshold once the exchange settles
+    return see(board, chessmove) >= threshold;
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    fn see_of(fen: &str, uci: &str) -> Score{
+        let board = Board::from_str(fen).expect("test positions should be legal");
+        let chessmove = ChessMove::from_str(uci).expect("test moves should parse");
+        assert!(board.legal(chessmove), "{uci} is not legal in {fen}");
+        return see(&board, chessmove);
+    }
+
+    #[test]
+    fn undefended_pawn(){
+        assert_eq!(see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+    }
+
+    #[test]
+    fn knight_takes_defended_pawn_with_xrays_on_both_sides(){
+        //rook and queen stacked on the e file for white, bishop and queen on the long diagonal for black
+        assert_eq!(see_of("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"), -200);
+    }
+
+    #[test]
+    fn rook_behind_rook_wins_the_pawn(){
+        //without the x-ray from e1 this would look like losing the rook
+        assert_eq!(see_of("4k3/4r3/8/4p3/8/8/4R3/4R1K1 w - - 0 1", "e2e5"), 100);
+    }
+
+    #[test]
+    fn queen_takes_pawn_defended_by_pawn(){
+        assert_eq!(see_of("4k3/8/3p4/4p3/8/8/8/4QK2 w - - 0 1", "e1e5"), -800);
+    }
+
+    #[test]
+    fn en_passant(){
+        assert_eq!(see_of("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 100);
+        assert_eq!(see_of("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 2", "e5d6"), 0);
+    }
+
+    #[test]
+    fn promotions(){
+        assert_eq!(see_of("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), 800);
+        assert_eq!(see_of("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"), -100);
+        assert_eq!(see_of("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7a8q"), 1300);
+    }
+
+    #[test]
+    fn king_cannot_recapture_a_defended_piece(){
+        assert_eq!(see_of("8/8/8/8/8/4k3/3p4/3QK3 w - - 0 1", "d1d2"), 100);
+    }
+
+    #[test]
+    fn threshold(){
+        let board = Board::from_str("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1").unwrap();
+        let rxe5 = ChessMove::from_str("e1e5").unwrap();
+        assert!(see_ge(&board, rxe5, 0));
+        assert!(see_ge(&board, rxe5, 100));
+        assert!(!see_ge(&board, rxe5, 101));
+    }
+}
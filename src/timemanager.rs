@@ -0,0 +1,261 @@
+use std::time::Instant;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use chess::{Board, ChessMove, Piece, Rank};
+use crate::evaluation::Score;
+
+const MIN_TIME_LEFT_MS: u64 = 50;//never plan to use the last of the clock, the gui needs some slack too
+const STILL_PONDERING: u64 = u64::MAX;
+const MAX_MOVES_TO_GO: u64 = 50;//more than this and the gui is probably just telling us about a long time control
+
+pub struct TimeManager{
+    start: Instant,
+    base_soft_ms: u64,//don't start another iteration after this, grows when the search looks unsettled
+    hard_ms: u64,//abort in the middle of an iteration
+    max_scale: f32,//the soft limit can grow to at most this many times the base
+    instability: f32,
+    ponderhit_ms: Option<Arc<AtomicU64>>//ms after start that the ponderhit came in, STILL_PONDERING until then
+}
+
+pub struct PonderHit{
+    //the uci thread keeps this to tell a pondering search that the clock is running now
+    start: Instant,
+    ponderhit_ms: Arc<AtomicU64>
+}
+
+impl PonderHit{
+    pub fn hit(&self){
+        self.ponderhit_ms.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+impl TimeManager{
+    pub fn infinite() -> Self{
+        //no clock, something else (stop, depth, nodes, mate) ends the search
+        TimeManager {
+            start: Instant::now(),
+            base_soft_ms: u32::MAX as u64,
+            hard_ms: u32::MAX as u64,
+            max_scale: 1.0,
+            instability: 1.0,
+            ponderhit_ms: None
+        }
+    }
+
+    pub fn fixed(movetime_ms: u64, move_overhead_ms: u64) -> Self{
+        //go movetime, use all of it and no more
+        let limit = movetime_ms.saturating_sub(move_overhead_ms).max(1);
+        TimeManager {
+            start: Instant::now(),
+            base_soft_ms: limit,
+            hard_ms: limit,
+            max_scale: 1.0,
+            instability: 1.0,
+            ponderhit_ms: None
+        }
+    }
+
+    pub fn from_clock(time_left_ms: u64, increment_ms: u64, movestogo: Option<u64>, move_overhead_ms: u64,
+                        time_divisor: u64, max_scale: f32) -> Self{
+        //assume time_divisor more moves unless the gui tells us when the next time control is
+        let available = time_left_ms.saturating_sub(move_overhead_ms + MIN_TIME_LEFT_MS).max(1);
+        let moves_left = match movestogo{
+            Some(mtg) => mtg.clamp(1, MAX_MOVES_TO_GO),
+            None => time_divisor.max(1)
+        };
+        let base_soft = (available / moves_left + increment_ms / 2).min(available);
+        let hard = ((base_soft as f32 * max_scale) as u64).min(available).max(base_soft);
+        TimeManager {
+            start: Instant::now(),
+            base_soft_ms: base_soft.max(1),
+            hard_ms: hard.max(1),
+            max_scale: max_scale.max(1.0),
+            instability: 1.0,
+            ponderhit_ms: None
+        }
+    }
+
+    pub fn ponder(&mut self) -> PonderHit{
+        //go ponder: search for as long as it takes until the PonderHit is hit, then play by these limits
+        let ponderhit_ms = Arc::new(AtomicU64::new(STILL_PONDERING));
+        self.ponderhit_ms = Some(ponderhit_ms.clone());
+        return PonderHit { start: self.start, ponderhit_ms: ponderhit_ms };
+    }
+
+    pub fn is_pondering(&self) -> bool{
+        match &self.ponderhit_ms{
+            Some(p) => return p.load(Ordering::Relaxed) == STILL_PONDERING,
+            None => return false
+        }
+    }
+
+    pub fn start(&self) -> &Instant{
+        //when the go command came in, even if we were pondering
+        return &self.start;
+    }
+
+    pub fn elapsed_ms(&self) -> u64{
+        //time used against our own clock, counted from the ponderhit if we were pondering and nothing before it
+        let total = self.start.elapsed().as_millis() as u64;
+        match &self.ponderhit_ms{
+            Some(p) => {
+                let hit = p.load(Ordering::Relaxed);
+                if hit == STILL_PONDERING{
+                    return 0;
+                }
+                return total.saturating_sub(hit);
+            }
+            None => return total
+        }
+    }
+
+    pub fn soft_limit_ms(&self) -> u64{
+        let scaled = (self.base_soft_ms as f32 * self.instability.min(self.max_scale)) as u64;
+        return scaled.min(self.hard_ms);
+    }
+
+    pub fn hard_limit_ms(&self) -> u64{
+        return self.hard_ms;
+    }
+
+    pub fn should_start_iteration(&self) -> bool{
+        return self.is_pondering() || self.elapsed_ms() < self.soft_limit_ms();
+    }
+
+    pub fn hard_limit_reached(&self) -> bool{
+        return !self.is_pondering() && self.elapsed_ms() >= self.hard_ms;
+    }
+
+    pub fn adjust_for_instability(&mut self, board: &Board, best_move: Option<ChessMove>, best_move_changed: bool, score_history: &[Score]){
+        //called after every iteration, each of these makes us want to think a bit longer
+        //best move instability
+        if best_move_changed{
+            self.instability *= 1.30;
+        }
+
+        //score history instability
+        if score_history.len() >= 3{
+            let recent = &score_history[score_history.len()-3..];
+            let max_recent_score = recent.iter().max().unwrap_or(&0);
+            let min_recent_score = recent.iter().min().unwrap_or(&0);
+            if max_recent_score - min_recent_score > 1000{//this 10 pawn number is arbitrary
+                self.instability *= 1.25;
+            }
+        }
+
+        //close alternatives check, not currently possible
+
+        //tactical indicator check
+        //if we are currently in check
+        if board.checkers().popcnt() > 0{
+            self.instability *= 1.2;
+        }
+
+        match best_move{
+            Some(bm) => {
+                //if our best move puts the opponent in check
+                if board.make_move_new(bm).checkers().popcnt() > 0{
+                    self.instability *= 1.2;
+                }
+
+                //if our best move is a capture
+                if board.piece_on(bm.get_dest()).is_some(){
+                    self.instability *= 1.2;
+                }
+
+                //if our best move is a promotion
+                if board.piece_on(bm.get_source()) == Some(Piece::Pawn) && (bm.get_dest().get_rank() == Rank::Eighth || bm.get_dest().get_rank() == Rank::First){
+                    self.instability *= 1.2;
+                }
+            }
+            None => {}
+        }
+    }
+
+    pub fn instability(&self) -> f32{
+        return self.instability;
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn clock_share_after_overhead_and_floor(){
+        //60s - 10ms overhead - 50ms floor = 59940, split over 20 moves
+        let tm = TimeManager::from_clock(60_000, 0, None, 10, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 2997);
+        assert_eq!(tm.hard_limit_ms(), 14985);
+    }
+
+    #[test]
+    fn half_the_increment_is_added(){
+        let tm = TimeManager::from_clock(10_000, 1000, None, 0, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 9950 / 20 + 500);
+        assert_eq!(tm.hard_limit_ms(), (9950 / 20 + 500) * 5);
+    }
+
+    #[test]
+    fn movestogo_replaces_the_divisor(){
+        let tm = TimeManager::from_clock(60_000, 0, Some(40), 10, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 59940 / 40);
+
+        //silly values get clamped
+        let tm = TimeManager::from_clock(60_000, 0, Some(1000), 10, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 59940 / MAX_MOVES_TO_GO);
+        let tm = TimeManager::from_clock(60_000, 0, Some(0), 10, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 59940);
+        assert_eq!(tm.hard_limit_ms(), 59940);
+    }
+
+    #[test]
+    fn never_plans_past_the_clock(){
+        //hard limit is capped by what is left, and nearly no time still gives a limit of at least 1ms
+        let tm = TimeManager::from_clock(1000, 0, Some(1), 10, 20, 5.0);
+        assert_eq!(tm.hard_limit_ms(), 940);
+        let tm = TimeManager::from_clock(30, 0, None, 10, 20, 5.0);
+        assert_eq!(tm.soft_limit_ms(), 1);
+        assert_eq!(tm.hard_limit_ms(), 1);
+    }
+
+    #[test]
+    fn movetime_is_used_as_is(){
+        let tm = TimeManager::fixed(3000, 10);
+        assert_eq!(tm.soft_limit_ms(), 2990);
+        assert_eq!(tm.hard_limit_ms(), 2990);
+        assert_eq!(TimeManager::fixed(5, 10).hard_limit_ms(), 1);
+    }
+
+    #[test]
+    fn instability_grows_the_soft_limit_up_to_the_hard_one(){
+        let board = Board::from_str("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        let quiet_move = ChessMove::from_str("e1d1").unwrap();
+        let mut tm = TimeManager::from_clock(60_000, 0, None, 10, 20, 5.0);
+        tm.adjust_for_instability(&board, Some(quiet_move), true, &[]);
+        assert_eq!(tm.soft_limit_ms(), (2997.0 * 1.3_f32) as u64);
+        for _ in 0..20{
+            tm.adjust_for_instability(&board, Some(quiet_move), true, &[]);
+        }
+        assert_eq!(tm.soft_limit_ms(), tm.hard_limit_ms());
+    }
+
+    #[test]
+    fn no_limits_until_ponderhit(){
+        let mut tm = TimeManager::fixed(1, 0);
+        let ponder_hit = tm.ponder();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(tm.is_pondering());
+        assert_eq!(tm.elapsed_ms(), 0);
+        assert!(tm.should_start_iteration());
+        assert!(!tm.hard_limit_reached());
+
+        ponder_hit.hit();
+        assert!(!tm.is_pondering());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(tm.hard_limit_reached());
+        assert!(!tm.should_start_iteration());
+    }
+}
@@ -0,0 +1,313 @@
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use chess::{ChessMove, Piece};
+use log::{debug,info,warn,error};
+use crate::evaluation::Score;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound{
+    Exact,
+    Lower,//the real score is at least this (we failed high)
+    Upper//the real score is at most this (we failed low)
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TTEntry{
+    pub key: u64,
+    pub depth: u8,
+    pub score: Score,
+    pub bound: Bound,
+    pub best_move: Option<ChessMove>,
+    pub age: u8
+}
+
+struct TTSlot{
+    //a torn write from another thread just looks like a key mismatch instead of a wrong result, so no locks
+    key_xor_data: AtomicU64,
+    data: AtomicU64
+}
+
+pub struct TranspositionTable{
+    slots: Vec<TTSlot>,
+    age: AtomicU8,
+    size_mb: usize
+}
+
+const SLOT_BYTES: usize = 16;
+const AGE_MASK: u8 = 0x3f;
+
+
+fn encode_move(chessmove: Option<ChessMove>) -> u64{
+    match chessmove{
+        Some(cm) => {
+            let promotion: u64 = match cm.get_promotion(){
+                Some(Piece::Knight) => 1,
+                Some(Piece::Bishop) => 2,
+                Some(Piece::Rook) => 3,
+                Some(Piece::Queen) => 4,
+                _ => 0
+            };
+            let bits = (cm.get_source().to_index() as u64) | ((cm.get_dest().to_index() as u64) << 6) | (promotion << 12);
+            return bits | (1 << 15);//so a1a1 isn't confused with no move
+        }
+        None => {
+            return 0;
+        }
+    }
+}
+
+fn decode_move(bits: u64) -> Option<ChessMove>{
+    if bits & (1 << 15) == 0{
+        return None;
+    }
+    let source = chess::ALL_SQUARES[(bits & 0x3f) as usize];
+    let dest = chess::ALL_SQUARES[((bits >> 6) & 0x3f) as usize];
+    let promotion = match (bits >> 12) & 0x7{
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None
+    };
+    return Some(ChessMove::new(source, dest, promotion));
+}
+
+fn encode_bound(bound: Bound) -> u64{
+    match bound{
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3
+    }
+}
+
+fn decode_bound(bits: u64) -> Option<Bound>{
+    match bits{
+        1 => Some(Bound::Exact),
+        2 => Some(Bound::Lower),
+        3 => Some(Bound::Upper),
+        _ => None//0 means the slot was never written
+    }
+}
+
+impl TTEntry{
+    fn pack(&self) -> u64{
+        //low bit first: score 32 (mates counted from this node), move 16, depth 8, bound 2, age 6
+        return (self.score as u32 as u64)
+            | (encode_move(self.best_move) << 32)
+            | ((self.depth as u64) << 48)
+            | (encode_bound(self.bound) << 56)
+            | (((self.age & AGE_MASK) as u64) << 58);
+    }
+
+    fn unpack(key: u64, data: u64) -> Option<Self>{
+        let bound = decode_bound((data >> 56) & 0x3)?;
+        Some(TTEntry {
+            key: key,
+            depth: ((data >> 48) & 0xff) as u8,
+            score: (data & 0xffff_ffff) as u32 as Score,
+            bound: bound,
+            best_move: decode_move((data >> 32) & 0xffff),
+            age: ((data >> 58) & (AGE_MASK as u64)) as u8
+        })
+    }
+}
+
+
+impl TranspositionTable{
+    pub fn new(size_mb: usize) -> Self{
+        let num_slots = (size_mb.max(1) * 1024 * 1024) / SLOT_BYTES;
+        let mut slots = Vec::with_capacity(num_slots);
+        for _ in 0..num_slots{
+            slots.push(TTSlot { key_xor_data: AtomicU64::new(0), data: AtomicU64::new(0) });
+        }
+        info!("Transposition table: {} MB, {} entries", size_mb, num_slots);
+        TranspositionTable {
+            slots: slots,
+            age: AtomicU8::new(0),
+            size_mb: size_mb
+        }
+    }
+
+    fn index(&self, key: u64) -> usize{
+        //maps the key onto 0..len without needing a power of two size
+        return (((key as u128) * (self.slots.len() as u128)) >> 64) as usize;
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TTEntry>{
+        let slot = &self.slots[self.index(key)];
+        let data = slot.data.load(Ordering::Relaxed);
+        let key_xor_data = slot.key_xor_data.load(Ordering::Relaxed);
+        if key_xor_data ^ data != key{
+            return None;
+        }
+        return TTEntry::unpack(key, data);
+    }
+
+    pub fn store(&self, key: u64, depth: u8, score: Score, bound: Bound, best_move: Option<ChessMove>){
+        let slot = &self.slots[self.index(key)];
+        let age = self.age.load(Ordering::Relaxed) & AGE_MASK;
+
+        //depth preferred: only overwrite a deeper result if it is left over from an older search
+        let old_data = slot.data.load(Ordering::Relaxed);
+        let old_key = slot.key_xor_data.load(Ordering::Relaxed) ^ old_data;
+        match TTEntry::unpack(old_key, old_data){
+            Some(old) if old.age == age && depth < old.depth => {
+                return;
+            }
+            _ => {}
+        }
+
+        let mut best_move = best_move;
+        if best_move.is_none() && old_key == key{
+            //keep the move we already knew for this position instead of wiping it
+            best_move = TTEntry::unpack(old_key, old_data).and_then(|e| e.best_move);
+        }
+
+        let entry = TTEntry { key: key, depth: depth, score: score, bound: bound, best_move: best_move, age: age };
+        let data = entry.pack();
+        slot.key_xor_data.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+
+    pub fn new_search(&self){
+        //entries from earlier searches become fair game for replacement
+        let age = self.age.load(Ordering::Relaxed);
+        self.age.store(age.wrapping_add(1) & AGE_MASK, Ordering::Relaxed);
+    }
+
+    pub fn clear(&self){
+        for slot in &self.slots{
+            slot.key_xor_data.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    pub fn hashfull(&self) -> u32{
+        //permille of the first 1000 slots holding something from this search, what uci wants
+        let age = self.age.load(Ordering::Relaxed) & AGE_MASK;
+        let sample = self.slots.len().min(1000);
+        let mut used = 0;
+        for slot in &self.slots[..sample]{
+            let data = slot.data.load(Ordering::Relaxed);
+            let key = slot.key_xor_data.load(Ordering::Relaxed) ^ data;
+            match TTEntry::unpack(key, data){
+                Some(entry) if entry.age == age => {
+                    used += 1;
+                }
+                _ => {}
+            }
+        }
+        return (used * 1000 / sample.max(1)) as u32;
+    }
+}
+
+impl std::fmt::Display for TranspositionTable{
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result{
+        return writeln!(f, "--Transposition table: {} MB, {} entries, {} permille full",
+                        self.size_mb, self.slots.len(), self.hashfull());
+    }
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::str::FromStr;
+
+    fn mv(uci: &str) -> Option<ChessMove>{
+        return Some(ChessMove::from_str(uci).expect("test moves should parse"));
+    }
+
+    #[test]
+    fn pack_unpack_round_trip(){
+        let cases = [
+            (0, Bound::Exact, None, 0),
+            (-153, Bound::Upper, mv("e2e4"), 7),
+            (29990, Bound::Lower, mv("a7a8q"), 63),//mate score
+            (-29995, Bound::Exact, mv("h2h1n"), 1),//getting mated, with an underpromotion
+            (12, Bound::Lower, mv("a1a1"), 255),//a1a1 still reads back as a move, not as none
+            (-1, Bound::Upper, mv("b7c8r"), 30),
+            (400, Bound::Exact, mv("g7f8b"), 4)
+        ];
+        for (score, bound, best_move, depth) in cases{
+            let entry = TTEntry { key: 0x1234, depth: depth, score: score, bound: bound, best_move: best_move, age: 5 };
+            let back = TTEntry::unpack(0x1234, entry.pack()).expect("a written entry should unpack");
+            assert_eq!(back.score, score);
+            assert_eq!(back.bound, bound);
+            assert_eq!(back.best_move, best_move);
+            assert_eq!(back.depth, depth);
+            assert_eq!(back.age, 5);
+        }
+    }
+
+    #[test]
+    fn empty_slot_is_a_miss(){
+        let tt = TranspositionTable::new(1);
+        assert!(tt.probe(0).is_none());
+        assert!(tt.probe(0xdead_beef).is_none());
+    }
+
+    #[test]
+    fn deeper_entry_survives_within_a_search(){
+        let tt = TranspositionTable::new(1);
+        let key = 0x9d39_247e_3377_6d41;
+        tt.store(key, 8, 50, Bound::Exact, mv("e2e4"));
+        tt.store(key, 3, -20, Bound::Upper, mv("d2d4"));
+        let entry = tt.probe(key).expect("stored entry should be found");
+        assert_eq!(entry.depth, 8);
+        assert_eq!(entry.best_move, mv("e2e4"));
+
+        //equal or deeper replaces
+        tt.store(key, 8, 35, Bound::Lower, mv("g1f3"));
+        assert_eq!(tt.probe(key).unwrap().best_move, mv("g1f3"));
+    }
+
+    #[test]
+    fn old_entries_get_replaced_by_shallower_ones(){
+        let tt = TranspositionTable::new(1);
+        let key = 0x2af7_3980_05aa_a5c7;
+        tt.store(key, 10, 50, Bound::Exact, mv("e2e4"));
+        tt.new_search();
+        tt.store(key, 2, 10, Bound::Lower, mv("c2c4"));
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.depth, 2);
+        assert_eq!(entry.best_move, mv("c2c4"));
+        assert_eq!(entry.age, 1);
+    }
+
+    #[test]
+    fn storing_without_a_move_keeps_the_old_one(){
+        let tt = TranspositionTable::new(1);
+        let key = 0x44db_0150_2462_3547;
+        tt.store(key, 4, 50, Bound::Lower, mv("e2e4"));
+        tt.store(key, 5, 10, Bound::Upper, None);
+        let entry = tt.probe(key).unwrap();
+        assert_eq!(entry.depth, 5);
+        assert_eq!(entry.best_move, mv("e2e4"));
+    }
+
+    #[test]
+    fn key_data_mismatch_is_a_miss(){
+        let tt = TranspositionTable::new(1);
+        let key = 0x9c15_f73e_62a7_6ae2;
+        tt.store(key, 6, 75, Bound::Exact, mv("e2e4"));
+        assert!(tt.probe(key).is_some());
+
+        //a different key landing in the same slot doesn't match
+        assert!(tt.probe(key ^ 1).is_none());
+
+        //a torn write (data from one store, key half from another) doesn't either
+        let slot = &tt.slots[tt.index(key)];
+        slot.data.store(slot.data.load(Ordering::Relaxed) ^ (1 << 3), Ordering::Relaxed);
+        assert!(tt.probe(key).is_none());
+    }
+
+    #[test]
+    fn clear_empties_the_table(){
+        let tt = TranspositionTable::new(1);
+        let key = 0x7583_4465_489c_0c89;
+        tt.store(key, 6, 75, Bound::Exact, mv("e2e4"));
+        tt.clear();
+        assert!(tt.probe(key).is_none());
+    }
+}
@@ -1,3 +1,6 @@
+use std::str::FromStr;
+use chess::ChessMove;
+
 
 pub struct UCIGoCommand{
     pub ponder: Option<bool>,
@@ -7,10 +10,11 @@ pub struct UCIGoCommand{
     pub binc: Option<i32>,
     pub movestogo: Option<i32>,
     pub depth: Option<i32>,
-    pub nodes: Option<i32>,
+    pub nodes: Option<u64>,
     pub mate: Option<i32>,
     pub movetime: Option<i32>,
-    pub infinite: Option<bool>
+    pub infinite: Option<bool>,
+    pub searchmoves: Option<Vec<ChessMove>>
 }
 
 impl UCIGoCommand{
@@ -22,10 +26,11 @@ impl UCIGoCommand{
         let mut binc: Option<i32> = None;
         let mut movestogo: Option<i32> = None;        
         let mut depth: Option<i32> = None;
-        let mut nodes: Option<i32> = None;
+        let mut nodes: Option<u64> = None;
         let mut mate: Option<i32> = None;
         let mut movetime: Option<i32> = None;
         let mut infinite: Option<bool> = None;
+        let mut searchmoves: Option<Vec<ChessMove>> = None;
         
         let mut i: usize = 1;
 
@@ -33,6 +38,7 @@ impl UCIGoCommand{
             match tokens[i] {
                 "ponder" =>{
                     ponder = Some(true);
+                    i += 1;
                 }                
                 "wtime" if i + 1 < tokens.len() => {
                     wtime = tokens[i + 1].parse().ok();
@@ -74,6 +80,21 @@ impl UCIGoCommand{
                     infinite = Some(true);
                     i += 1;
                 }
+                "searchmoves" => {
+                    //every token after this that parses as a move, stops at the next keyword
+                    let mut moves: Vec<ChessMove> = vec![];
+                    i += 1;
+                    while i < tokens.len(){
+                        match ChessMove::from_str(tokens[i]){
+                            Ok(cm) => {
+                                moves.push(cm);
+                                i += 1;
+                            }
+                            Err(_) => break
+                        }
+                    }
+                    searchmoves = Some(moves);
+                }
                 _ => i += 1,
             }
         }
@@ -89,7 +110,8 @@ impl UCIGoCommand{
             nodes: nodes, 
             mate: mate, 
             movetime: movetime,
-            infinite: infinite
+            infinite: infinite,
+            searchmoves: searchmoves
         }
     }
 }
\ No newline at end of file
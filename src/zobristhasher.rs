@@ -1,5 +1,4 @@
 use rand::prelude::*;
-use std::collections::HashMap;
 use chess::{BitBoard, Board, BoardStatus, ChessMove, MoveGen, Rank, Square};
 
 
@@ -211,17 +210,56 @@ const RANDOM_VALUES_POLYGLOT_ENPASSANT: [u64; 8] = [
 const RANDOM_VALUES_POLYGLOT_TURN: [u64; 1] = [0xF8D626AAAF278509];
 
 
+fn piece_square_key(piece: chess::Piece, color: chess::Color, square: Square) -> u64{
+    //same layout as hash_board, pieces go black pawn, white pawn, black knight ... white king
+    let mut kind_of_piece = match piece{
+        chess::Piece::Pawn => 0,
+        chess::Piece::Knight => 2,
+        chess::Piece::Bishop => 4,
+        chess::Piece::Rook => 6,
+        chess::Piece::Queen => 8,
+        chess::Piece::King => 10,
+    };
+    if color == chess::Color::White{
+        kind_of_piece += 1;
+    }
+    let offset_piece = 64*kind_of_piece + 8*square.get_rank().to_index() + square.get_file().to_index();
+    return RANDOM_VALUES_POLYGLOT_PIECE[offset_piece];
+}
+
+fn castle_key(board: &Board) -> u64{
+    let mut key: u64 = 0;
+    let rights = [(chess::Color::White, 0), (chess::Color::Black, 2)];
+    for (color, offset) in rights{
+        let castle_rights = board.castle_rights(color);
+        if castle_rights.has_kingside(){
+            key ^= RANDOM_VALUES_POLYGLOT_CASTLE[offset];
+        }
+        if castle_rights.has_queenside(){
+            key ^= RANDOM_VALUES_POLYGLOT_CASTLE[offset + 1];
+        }
+    }
+    return key;
+}
+
+fn enpassant_key(board: &Board) -> u64{
+    match board.en_passant(){
+        Some(sq) => RANDOM_VALUES_POLYGLOT_ENPASSANT[sq.get_file().to_index()],
+        None => 0
+    }
+}
+
+
+#[derive(Clone)]
 pub struct ZobristHasher{
-    pub gamestate_hashmap: HashMap<u64,u8>,
+    //just the keys, which positions came up in the game is kept by GameHistory
 }
 
 impl ZobristHasher{
     
     
     pub fn new() -> Self{
-        ZobristHasher { 
-            gamestate_hashmap: HashMap::new()
-        }
+        ZobristHasher {}
     }
 
 
@@ -255,7 +293,7 @@ impl ZobristHasher{
                         }
                         
                         let offset_piece = 64*kind_of_piece + 8*rank + file;
-                        piece_key = piece_key ^ RANDOM_VALUES_POLYGLOT_PIECE[offset_piece];
+                        piece_key ^= RANDOM_VALUES_POLYGLOT_PIECE[offset_piece];
                     }
                     None => {}
                 }
@@ -270,28 +308,28 @@ impl ZobristHasher{
         else{
             match board.castle_rights(chess::Color::White){
                 chess::CastleRights::KingSide => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[0];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[0];
                 }
                 chess::CastleRights::QueenSide => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[1];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[1];
                 }
                 chess::CastleRights::Both => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[0];
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[1];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[0];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[1];
                 }
                 chess::CastleRights::NoRights => {}//do nothing, we already handled the both colors none case above
             }
 
             match board.castle_rights(chess::Color::Black){
                 chess::CastleRights::KingSide => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[2];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[2];
                 }
                 chess::CastleRights::QueenSide => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[3];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[3];
                 }
                 chess::CastleRights::Both => {
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[2];
-                    castle_key = castle_key ^ RANDOM_VALUES_POLYGLOT_CASTLE[3];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[2];
+                    castle_key ^= RANDOM_VALUES_POLYGLOT_CASTLE[3];
                 }
                 chess::CastleRights::NoRights => {}//do nothing, we already handled the both colors none case above
             }
@@ -300,7 +338,7 @@ impl ZobristHasher{
         match board.en_passant(){
             Some(sq) => {
                 let file_idx = sq.get_file().to_index();
-                enpassant_key = enpassant_key ^ RANDOM_VALUES_POLYGLOT_ENPASSANT[file_idx];
+                enpassant_key ^= RANDOM_VALUES_POLYGLOT_ENPASSANT[file_idx];
             }
             None => {
                 enpassant_key = 0;
@@ -325,30 +363,108 @@ impl ZobristHasher{
     
     }
 
-    pub fn check_table(&self, board_hash: u64) -> u8{
-        let count = self.gamestate_hashmap.get(&board_hash).unwrap_or(&0);
+    pub fn hash_after_move(&self, board: &Board, board_hash: u64, chessmove: ChessMove, new_board: &Board) -> u64{
+        //same key as hash_board(new_board), but only touching what the move changed
+        //new_board is only read for its castle rights and en passant square, no looping over squares
+        let source = chessmove.get_source();
+        let dest = chessmove.get_dest();
+        let mover = board.side_to_move();
+        let piece = board.piece_on(source).expect("there should always be a piece at the source of a move");
 
-        return *count as u8;
-    }
+        let mut key = board_hash;
 
-    pub fn insert_board(&mut self, board: &Board){
-        let h = self.hash_board(board);
-        let boardcount = self.gamestate_hashmap.entry(h).or_insert(0);
-        *boardcount += 1;
-        //insert a 0 if its not in table, and then add 1 to it
-    }
+        //the moving piece leaves its square and lands as itself or its promotion
+        key ^= piece_square_key(piece, mover, source);
+        key ^= piece_square_key(chessmove.get_promotion().unwrap_or(piece), mover, dest);
 
-    pub fn to_string(&mut self) -> String{
-        let mut s: String = "--Count-----Hash---------------------\n".to_owned();
-        
-        for (h,count) in &self.gamestate_hashmap{
-            let ss: String = format!("--{}-------- {:x}\n",count,h);
-            s.push_str(&ss);
+        match board.piece_on(dest){
+            Some(captured) => {
+                key ^= piece_square_key(captured, !mover, dest);
+            }
+            None => {
+                if piece == chess::Piece::Pawn && source.get_file() != dest.get_file(){
+                    //en passant, the captured pawn is beside us not on dest
+                    let captured_sq = Square::make_square(source.get_rank(), dest.get_file());
+                    key ^= piece_square_key(chess::Piece::Pawn, !mover, captured_sq);
+                }
+            }
         }
 
-        return s;
+        if piece == chess::Piece::King && (source.get_file().to_index() as i32 - dest.get_file().to_index() as i32).abs() == 2{
+            //castling is written as the king move, the rook comes along
+            let (rook_from, rook_to) = if dest.get_file() == chess::File::G{
+                (chess::File::H, chess::File::F)
+            }else{
+                (chess::File::A, chess::File::D)
+            };
+            key ^= piece_square_key(chess::Piece::Rook, mover, Square::make_square(source.get_rank(), rook_from));
+            key ^= piece_square_key(chess::Piece::Rook, mover, Square::make_square(source.get_rank(), rook_to));
+        }
+
+        key ^= castle_key(board) ^ castle_key(new_board);
+        key ^= enpassant_key(board) ^ enpassant_key(new_board);
+        key ^= RANDOM_VALUES_POLYGLOT_TURN[0];
+
+        debug_assert_eq!(key, self.hash_board(new_board), "incremental hash disagrees with hash_board after {}", chessmove);
+
+        return key;
+    }
+
+    pub fn hash_after_null_move(&self, board: &Board, board_hash: u64, new_board: &Board) -> u64{
+        //passing only flips the side to move and clears any en passant square
+        let key = board_hash ^ RANDOM_VALUES_POLYGLOT_TURN[0] ^ enpassant_key(board) ^ enpassant_key(new_board);
+        debug_assert_eq!(key, self.hash_board(new_board), "incremental hash disagrees with hash_board after a null move");
+        return key;
     }
 
 
+}
+
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::str::FromStr;
+
+    #[test]
+    fn polyglot_reference_keys(){
+        //keys straight from the polyglot book format page
+        let hasher = ZobristHasher::new();
+        let mut board = Board::default();
+        assert_eq!(hasher.hash_board(&board), 0x463b96181691fc9c);
 
-}
\ No newline at end of file
+        let moves = ["e2e4", "d7d5", "e4e5", "f7f5", "e1e2", "e8f7"];
+        let keys: [u64; 6] = [0x823c9b50fd114196, 0x0756b94461c50fb0, 0x662fafb965db29d4,
+                              0x22a48b5a8e47ff78, 0x652a607ca3f242c1, 0x00fdd303c946bdd9];
+        for (m, expected) in moves.iter().zip(keys){
+            let cm = ChessMove::from_str(m).unwrap();
+            let new_board = board.make_move_new(cm);
+            let h = hasher.hash_after_move(&board, hasher.hash_board(&board), cm, &new_board);
+            assert_eq!(h, expected, "after {m}");
+            board = new_board;
+        }
+    }
+
+    #[test]
+    fn incremental_hash_matches_full_hash_over_random_games(){
+        let hasher = ZobristHasher::new();
+        let mut rng = StdRng::seed_from_u64(0x6e756c6c);
+
+        for _game in 0..200{
+            let mut board = Board::default();
+            let mut key = hasher.hash_board(&board);
+            for _ply in 0..200{
+                let moves: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+                if moves.is_empty(){
+                    break;
+                }
+                let cm = moves[rng.gen_range(0..moves.len())];
+                let new_board = board.make_move_new(cm);
+                key = hasher.hash_after_move(&board, key, cm, &new_board);
+                assert_eq!(key, hasher.hash_board(&new_board), "{} then {}", board, cm);
+                board = new_board;
+            }
+        }
+    }
+}